@@ -34,7 +34,7 @@ CREATE TABLE IF NOT EXISTS filtermeta(
     fid INTEGER PRIMARY KEY AUTOINCREMENT,
     name TEXT NOT NULL);
 
-INSERT OR IGNORE INTO filtermeta VALUES (0,"全部\");
-INSERT OR IGNORE INTO filtermeta VALUES (1,"特别关注\");
+INSERT OR IGNORE INTO filtermeta (fid, name) VALUES (0,"全部\");
+INSERT OR IGNORE INTO filtermeta (fid, name) VALUES (1,"特别关注\");
 
 COMMIT;
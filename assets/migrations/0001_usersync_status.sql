@@ -0,0 +1,3 @@
+ALTER TABLE usersync ADD COLUMN status INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE usersync ADD COLUMN status_ts INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE usersync ADD COLUMN status_cnt INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,9 @@
+CREATE TABLE IF NOT EXISTS userinfo_history(
+    id INTEGER NOT NULL,
+    name TEXT NOT NULL,
+    face_url TEXT NOT NULL,
+    live_room_url TEXT,
+    timestamp INTEGER NOT NULL);
+CREATE INDEX IF NOT EXISTS userinfo_history_id ON userinfo_history(id, timestamp);
+
+INSERT INTO userinfo_history SELECT id, name, face_url, live_room_url, 0 FROM userinfo;
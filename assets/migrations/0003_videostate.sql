@@ -0,0 +1,8 @@
+CREATE TABLE IF NOT EXISTS videostate(
+    vid TEXT PRIMARY KEY,
+    state INTEGER NOT NULL DEFAULT 0,
+    mtime INTEGER NOT NULL);
+CREATE INDEX IF NOT EXISTS videoowner_uid ON videoowner(uid, timestamp);
+-- videos known before states are kept have been seen already
+INSERT OR IGNORE INTO videostate (vid, state, mtime)
+    SELECT vid, 1, CAST(strftime('%s', 'now') AS INTEGER) FROM videoinfo;
@@ -0,0 +1,2 @@
+ALTER TABLE filtermeta ADD COLUMN visit_ts INTEGER NOT NULL DEFAULT 0;
+UPDATE filtermeta SET visit_ts=CAST(strftime('%s', 'now') AS INTEGER);
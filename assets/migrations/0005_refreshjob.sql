@@ -0,0 +1,9 @@
+CREATE TABLE IF NOT EXISTS refreshjob(
+    uid INTEGER PRIMARY KEY,
+    kind INTEGER NOT NULL DEFAULT 0,
+    priority INTEGER NOT NULL DEFAULT 0,
+    not_before INTEGER NOT NULL DEFAULT 0,
+    attempts INTEGER NOT NULL DEFAULT 0,
+    last_error TEXT,
+    ctime INTEGER NOT NULL);
+CREATE INDEX IF NOT EXISTS refreshjob_due ON refreshjob(not_before, priority);
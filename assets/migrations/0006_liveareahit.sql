@@ -0,0 +1,7 @@
+CREATE TABLE IF NOT EXISTS liveareahit(
+    parent_id INTEGER NOT NULL,
+    area_id INTEGER NOT NULL,
+    name TEXT NOT NULL DEFAULT '',
+    hits INTEGER NOT NULL DEFAULT 0,
+    mtime INTEGER NOT NULL,
+    PRIMARY KEY (parent_id, area_id));
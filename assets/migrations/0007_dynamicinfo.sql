@@ -0,0 +1,9 @@
+CREATE TABLE IF NOT EXISTS dynamicinfo(
+    did TEXT PRIMARY KEY,
+    uid INTEGER NOT NULL,
+    dtype TEXT NOT NULL,
+    text TEXT NOT NULL,
+    images TEXT NOT NULL,
+    timestamp INTEGER NOT NULL);
+CREATE INDEX IF NOT EXISTS dynamicinfo_uid ON dynamicinfo(uid, timestamp);
+CREATE INDEX IF NOT EXISTS dynamicinfo_ts ON dynamicinfo(timestamp);
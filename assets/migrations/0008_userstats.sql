@@ -0,0 +1,7 @@
+CREATE TABLE IF NOT EXISTS userstats(
+    uid INTEGER NOT NULL,
+    timestamp INTEGER NOT NULL,
+    followers INTEGER,
+    videos INTEGER,
+    live_viewers INTEGER,
+    PRIMARY KEY (uid, timestamp));
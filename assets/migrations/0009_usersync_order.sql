@@ -0,0 +1,2 @@
+ALTER TABLE usersync ADD COLUMN follow_ts INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE usersync ADD COLUMN live_start_ts INTEGER NOT NULL DEFAULT 0;
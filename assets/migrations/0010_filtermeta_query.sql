@@ -0,0 +1 @@
+ALTER TABLE filtermeta ADD COLUMN query TEXT;
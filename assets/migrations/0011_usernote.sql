@@ -0,0 +1,9 @@
+CREATE TABLE IF NOT EXISTS usernote(
+    uid INTEGER PRIMARY KEY,
+    note TEXT NOT NULL,
+    mtime INTEGER NOT NULL);
+CREATE TABLE IF NOT EXISTS usertag(
+    uid INTEGER NOT NULL,
+    tag TEXT NOT NULL,
+    UNIQUE(uid, tag));
+CREATE INDEX IF NOT EXISTS usertag_tag ON usertag(tag);
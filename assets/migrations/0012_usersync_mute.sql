@@ -0,0 +1,2 @@
+ALTER TABLE usersync ADD COLUMN mute_until INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE usersync ADD COLUMN mute_pause INTEGER NOT NULL DEFAULT 0;
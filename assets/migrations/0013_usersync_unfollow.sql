@@ -0,0 +1 @@
+ALTER TABLE usersync ADD COLUMN unfollow_ts INTEGER NOT NULL DEFAULT 0;
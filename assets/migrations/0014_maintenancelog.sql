@@ -0,0 +1,3 @@
+CREATE TABLE IF NOT EXISTS maintenancelog(
+    started INTEGER PRIMARY KEY,
+    report TEXT NOT NULL);
@@ -0,0 +1,7 @@
+CREATE TABLE IF NOT EXISTS imagecache(
+    hash TEXT PRIMARY KEY,
+    url TEXT NOT NULL,
+    mime TEXT NOT NULL DEFAULT '',
+    bytes INTEGER NOT NULL DEFAULT 0,
+    atime INTEGER NOT NULL DEFAULT 0);
+CREATE INDEX IF NOT EXISTS imagecache_atime ON imagecache(atime);
@@ -0,0 +1,7 @@
+CREATE TABLE IF NOT EXISTS liveareauser(
+    parent_id INTEGER NOT NULL,
+    area_id INTEGER NOT NULL,
+    uid INTEGER NOT NULL,
+    period INTEGER NOT NULL,
+    PRIMARY KEY (parent_id, area_id, uid));
+DELETE FROM liveareahit;
@@ -0,0 +1,165 @@
+//! Optional settings, read once from `./.cache/config.json` . A missing file or field falls
+//! back to the default.
+use crate::engine::{live::LivePollConfig, policy::Policy, sweep::LiveArea};
+use serde_derive::{Deserialize, Serialize};
+
+pub const CONFIG_PATH: &str = "./.cache/config.json";
+
+lazy_static::lazy_static! {
+    static ref CONFIG: Config = Config::load();
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Config {
+    /// Time-of-day refresh policies, the first one covering the current time wins.
+    pub schedule: Vec<Policy>,
+    /// Live areas swept besides the learned ones.
+    pub live_areas: Vec<LiveArea>,
+    /// Batched live status polling of followed users.
+    pub live_poll: LivePollConfig,
+    /// Fetch dynamics of users on refresh.
+    pub dynamics: bool,
+    /// Retention of user stats samples.
+    pub stats: StatsConfig,
+    /// Database pruning and vacuum.
+    pub maintenance: MaintenanceConfig,
+    /// Scheduled online backups of the database.
+    pub backup: BackupConfig,
+    /// Local cache of avatars and covers.
+    pub image_cache: ImageCacheConfig,
+    /// Directory holding `templates/` and `static/` to serve instead of the embedded ones,
+    /// reloaded on every request.
+    pub assets_dir: Option<String>,
+    /// UI locale, `zh-CN` or `en` , overriding `Accept-Language` of browsers.
+    pub locale: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct StatsConfig {
+    /// Samples within these days are kept as is, older ones are downsampled to one a day.
+    pub raw_days: i64,
+    /// Samples older than these days are dropped.
+    pub keep_days: i64,
+}
+
+impl Default for StatsConfig {
+    fn default() -> Self {
+        Self {
+            raw_days: 7,
+            keep_days: 365,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct MaintenanceConfig {
+    /// Least hours between two automatic runs, which only happen in slow refresh status.
+    pub interval_hours: i64,
+    /// Latest videos kept for each user, 0 keeps all.
+    pub keep_videos: i64,
+    /// Users unfollowed longer than these days are purged, 0 keeps them.
+    pub unfollowed_days: i64,
+    /// Free pages returned to the file system at most per run, 0 never vacuums.
+    pub vacuum_pages: i64,
+}
+
+impl Default for MaintenanceConfig {
+    fn default() -> Self {
+        Self {
+            interval_hours: 24,
+            keep_videos: 0,
+            unfollowed_days: 0,
+            vacuum_pages: 1000,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct BackupConfig {
+    pub dir: String,
+    /// Least hours between two scheduled backups, 0 disables them.
+    pub interval_hours: i64,
+    /// Latest backups kept.
+    pub keep: usize,
+}
+
+impl Default for BackupConfig {
+    fn default() -> Self {
+        Self {
+            dir: if cfg!(test) {
+                std::env::temp_dir()
+                    .join("hobob-backup")
+                    .to_string_lossy()
+                    .into_owned()
+            } else {
+                String::from("./.cache/backup")
+            },
+            interval_hours: 24,
+            keep: 7,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
+pub struct ImageCacheConfig {
+    /// Pages link images from the CDN directly if disabled.
+    pub enabled: bool,
+    pub dir: String,
+    /// Least recently used images are removed beyond this size.
+    pub max_mb: i64,
+}
+
+impl Default for ImageCacheConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            dir: String::from("./.cache/img"),
+            max_mb: 256,
+        }
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            schedule: Default::default(),
+            live_areas: vec![Default::default()],
+            live_poll: Default::default(),
+            dynamics: true,
+            stats: Default::default(),
+            maintenance: Default::default(),
+            backup: Default::default(),
+            image_cache: Default::default(),
+            assets_dir: None,
+            locale: None,
+        }
+    }
+}
+
+impl Config {
+    fn load() -> Self {
+        if cfg!(test) {
+            return Default::default();
+        }
+        let text = match std::fs::read_to_string(CONFIG_PATH) {
+            Ok(s) => s,
+            Err(e) => {
+                log::info!("No config loaded from {}: {}", CONFIG_PATH, e);
+                return Default::default();
+            }
+        };
+        serde_json::from_str(&text).unwrap_or_else(|e| {
+            log::error!("Parse config {} error(s): {}, use default", CONFIG_PATH, e);
+            Default::default()
+        })
+    }
+}
+
+pub fn get() -> &'static Config {
+    &CONFIG
+}
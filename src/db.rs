@@ -1,12 +1,18 @@
 use anyhow::{anyhow, Result};
 use chrono::{DateTime, TimeZone, Utc};
-use rusqlite::{params, Connection, Row};
+use rusqlite::{
+    params, params_from_iter, types::Value, Connection, OpenFlags, OptionalExtension, Row,
+};
 use serde_derive::{Deserialize, Serialize};
+use std::cmp::Ordering;
 use std::convert::TryFrom;
 use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
 use std::sync::Mutex;
+use utoipa::ToSchema;
 
-#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+#[derive(Debug, Deserialize, Serialize, Clone, Default, ToSchema)]
 pub struct UserInfo {
     pub id: i64,
     pub name: String,
@@ -17,7 +23,17 @@ pub struct UserInfo {
     pub live_entropy: Option<i64>,
 }
 
-#[derive(Clone)]
+/// A distinct profile of an upzhu, recorded when first seen.
+#[derive(Debug, Deserialize, Serialize, Clone, ToSchema)]
+pub struct UserInfoHistory {
+    pub id: i64,
+    pub name: String,
+    pub face_url: String,
+    pub live_room_url: Option<String>,
+    pub timestamp: i64,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, ToSchema)]
 pub struct UserSync {
     pub id: i64,
     pub enable: bool,
@@ -25,9 +41,31 @@ pub struct UserSync {
     pub ctimestamp: i64,
     pub new_video_ts: i64,
     pub new_video_title: String,
+    pub status: SyncStatus,
+    pub status_ts: i64,
+    pub status_cnt: i64,
+    /// Last time followed, 0 for users followed before it is recorded.
+    pub follow_ts: i64,
+    /// Start time of the live seen open, 0 if never seen.
+    pub live_start_ts: i64,
+    /// Hidden from lists and notifications until then.
+    pub mute_until: i64,
+    /// Not auto refreshed either while muted.
+    pub mute_pause: bool,
+    /// Last time unfollowed, 0 if never.
+    pub unfollow_ts: i64,
 }
 
-#[derive(Debug, Deserialize, Serialize, Clone)]
+/// Account state of an upzhu as seen by the last refreshes.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum SyncStatus {
+    Active,
+    Missing,
+    Banned,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, ToSchema)]
 pub struct VideoInfo {
     pub vid: String,
     pub title: String,
@@ -35,6 +73,106 @@ pub struct VideoInfo {
     pub utime: DateTime<Utc>,
 }
 
+/// Watching state of a video, videos without a recorded state are unseen.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum VideoState {
+    Unseen,
+    Seen,
+    Saved,
+    Dismissed,
+}
+
+/// A video with its owner and watching state.
+#[derive(Debug, Deserialize, Serialize, Clone, ToSchema)]
+pub struct StatedVideo {
+    pub video: VideoInfo,
+    pub uid: i64,
+    pub state: VideoState,
+}
+
+/// A dynamic (post) of an upzhu, _dtype_ is the lowercase dynamic type without
+/// `DYNAMIC_TYPE_` , e.g. `draw` , `word` , `av` or `forward` .
+#[derive(Debug, Deserialize, Serialize, Clone, ToSchema)]
+pub struct DynamicInfo {
+    pub did: String,
+    pub uid: i64,
+    pub dtype: String,
+    pub text: String,
+    pub images: Vec<String>,
+    pub timestamp: i64,
+}
+
+/// A sample of counters of an upzhu, taken on refresh. Counters failed to fetch are `None` .
+#[derive(Debug, Deserialize, Serialize, Clone, ToSchema)]
+pub struct UserStat {
+    pub uid: i64,
+    pub timestamp: i64,
+    pub followers: Option<i64>,
+    pub videos: Option<i64>,
+    pub live_viewers: Option<i64>,
+}
+
+/// What a maintenance run removed and found, see [`Maintenance::run`] .
+#[derive(Debug, Deserialize, Serialize, Clone, Default, ToSchema)]
+pub struct MaintenanceReport {
+    pub started: i64,
+    pub elapsed_ms: i64,
+    /// Users unfollowed for too long, purged.
+    pub purged_users: Vec<i64>,
+    /// Videos dropped beyond the kept latest ones of each user.
+    pub pruned_videos: usize,
+    /// Video infos and states no more owned by any user.
+    pub orphan_rows: usize,
+    /// Cached images never fetched for long, and image files no more cached.
+    pub pruned_images: usize,
+    /// Lines of `PRAGMA integrity_check` , `["ok"]` for a sound database.
+    pub integrity: Vec<String>,
+    pub freed_pages: i64,
+    pub page_count: i64,
+}
+
+/// Retention settings of a maintenance run, 0 disables the respective pruning.
+#[derive(Debug, Clone, Default)]
+pub struct Maintenance {
+    /// Latest videos kept for each user, saved ones are always kept.
+    pub keep_videos: i64,
+    /// Users unfollowed longer than this are purged.
+    pub unfollowed_secs: i64,
+    /// Free pages returned to the file system at most per run.
+    pub vacuum_pages: i64,
+    /// Directory of cached image files, whose files no more cached are removed.
+    pub image_dir: Option<PathBuf>,
+}
+
+/// A backup file of the cache database.
+#[derive(Debug, Deserialize, Serialize, Clone, ToSchema)]
+pub struct BackupFile {
+    pub name: String,
+    pub bytes: u64,
+    pub mtime: i64,
+}
+
+/// Online backups into _dir_ , only the latest _keep_ ones are kept.
+#[derive(Debug, Clone)]
+pub struct Backup {
+    pub dir: PathBuf,
+    pub keep: usize,
+}
+
+/// Free-form note and tags of an upzhu, empty if never annotated.
+#[derive(Debug, Deserialize, Serialize, Clone, Default, PartialEq, Eq, ToSchema)]
+pub struct UserNote {
+    #[serde(default)]
+    pub uid: i64,
+    #[serde(default)]
+    pub note: String,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub mtime: i64,
+}
+
 #[derive(Debug)]
 pub struct VideoOwner {
     pub uid: i64,
@@ -42,17 +180,164 @@ pub struct VideoOwner {
     pub timestamp: i64,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Deserialize, Serialize, Clone, ToSchema)]
 pub struct UserFilter {
     pub uid: i64,
     pub fid: i64,
     pub priority: i64,
 }
 
-#[derive(Debug, Deserialize, Serialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, Clone, ToSchema)]
 pub struct FilterMeta {
     pub fid: i64,
     pub name: String,
+    pub visit_ts: i64,
+    #[serde(default)]
+    pub counts: FilterCounts,
+    /// Saved query of a smart filter, whose members are not modifiable.
+    #[serde(default)]
+    pub query: Option<UserQuerySpec>,
+}
+
+/// Badge counts of a filter, `new_videos` counts videos uploaded since `visit_ts`.
+#[derive(Debug, Deserialize, Serialize, Clone, Default, PartialEq, Eq, ToSchema)]
+pub struct FilterCounts {
+    pub members: i64,
+    pub live: i64,
+    pub new_videos: i64,
+}
+
+/// Why a refresh job was queued.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum JobKind {
+    Manual,
+    Follow,
+}
+
+/// A pending refresh of an user, deduplicated per uid and kept across restarts until it is done,
+/// cancelled or has failed `JOB_MAX_ATTEMPTS` times.
+#[derive(Debug, Deserialize, Serialize, Clone, ToSchema)]
+pub struct RefreshJob {
+    pub uid: i64,
+    pub kind: JobKind,
+    pub priority: i64,
+    pub not_before: i64,
+    pub attempts: i64,
+    pub last_error: Option<String>,
+    pub ctime: i64,
+}
+
+/// A remote image known as _hash_ , stored on disk when _bytes_ is not 0.
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct CachedImage {
+    pub hash: String,
+    pub url: String,
+    pub mime: String,
+    pub bytes: i64,
+    pub atime: i64,
+}
+
+/// Count of followed users found living in a live area.
+#[derive(Debug, Deserialize, Serialize, Clone, ToSchema)]
+pub struct LiveAreaHit {
+    pub parent_id: i64,
+    pub area_id: i64,
+    pub name: String,
+    pub hits: i64,
+    pub mtime: i64,
+}
+
+impl Default for SyncStatus {
+    fn default() -> Self {
+        Self::Active
+    }
+}
+
+impl From<i64> for SyncStatus {
+    fn from(i: i64) -> Self {
+        match i {
+            1 => Self::Missing,
+            2 => Self::Banned,
+            _ => Self::Active,
+        }
+    }
+}
+
+impl From<SyncStatus> for i64 {
+    fn from(s: SyncStatus) -> Self {
+        match s {
+            SyncStatus::Active => 0,
+            SyncStatus::Missing => 1,
+            SyncStatus::Banned => 2,
+        }
+    }
+}
+
+impl std::fmt::Display for SyncStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Active => "active",
+            Self::Missing => "missing",
+            Self::Banned => "banned",
+        })
+    }
+}
+
+impl Default for VideoState {
+    fn default() -> Self {
+        Self::Unseen
+    }
+}
+
+impl From<i64> for VideoState {
+    fn from(i: i64) -> Self {
+        match i {
+            1 => Self::Seen,
+            2 => Self::Saved,
+            3 => Self::Dismissed,
+            _ => Self::Unseen,
+        }
+    }
+}
+
+impl From<VideoState> for i64 {
+    fn from(s: VideoState) -> Self {
+        match s {
+            VideoState::Unseen => 0,
+            VideoState::Seen => 1,
+            VideoState::Saved => 2,
+            VideoState::Dismissed => 3,
+        }
+    }
+}
+
+impl From<i64> for JobKind {
+    fn from(i: i64) -> Self {
+        match i {
+            1 => Self::Follow,
+            _ => Self::Manual,
+        }
+    }
+}
+
+impl From<JobKind> for i64 {
+    fn from(k: JobKind) -> Self {
+        match k {
+            JobKind::Manual => 0,
+            JobKind::Follow => 1,
+        }
+    }
+}
+
+impl JobKind {
+    /// Newly followed users are refreshed first.
+    pub fn priority(self) -> i64 {
+        match self {
+            Self::Manual => 1,
+            Self::Follow => 2,
+        }
+    }
 }
 
 impl Default for VideoInfo {
@@ -84,6 +369,18 @@ impl FromRow for UserInfo {
     }
 }
 
+impl FromRow for UserInfoHistory {
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        Ok(Self {
+            id: row.get(0)?,
+            name: row.get(1)?,
+            face_url: row.get(2)?,
+            live_room_url: row.get(3)?,
+            timestamp: row.get(4)?,
+        })
+    }
+}
+
 impl FromRow for UserSync {
     fn from_row(row: &Row) -> rusqlite::Result<Self> {
         Ok(Self {
@@ -93,6 +390,14 @@ impl FromRow for UserSync {
             ctimestamp: row.get(3)?,
             new_video_ts: row.get(4)?,
             new_video_title: row.get(5)?,
+            status: row.get::<_, i64>(6)?.into(),
+            status_ts: row.get(7)?,
+            status_cnt: row.get(8)?,
+            follow_ts: row.get(9)?,
+            live_start_ts: row.get(10)?,
+            mute_until: row.get(11)?,
+            mute_pause: row.get(12)?,
+            unfollow_ts: row.get(13)?,
         })
     }
 }
@@ -108,6 +413,79 @@ impl FromRow for VideoInfo {
     }
 }
 
+impl FromRow for StatedVideo {
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        Ok(Self {
+            video: VideoInfo::from_row(row)?,
+            uid: row.get(4)?,
+            state: row.get::<_, Option<i64>>(5)?.unwrap_or(0).into(),
+        })
+    }
+}
+
+impl FromRow for RefreshJob {
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        Ok(Self {
+            uid: row.get(0)?,
+            kind: row.get::<_, i64>(1)?.into(),
+            priority: row.get(2)?,
+            not_before: row.get(3)?,
+            attempts: row.get(4)?,
+            last_error: row.get(5)?,
+            ctime: row.get(6)?,
+        })
+    }
+}
+
+impl FromRow for CachedImage {
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        Ok(Self {
+            hash: row.get(0)?,
+            url: row.get(1)?,
+            mime: row.get(2)?,
+            bytes: row.get(3)?,
+            atime: row.get(4)?,
+        })
+    }
+}
+
+impl FromRow for LiveAreaHit {
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        Ok(Self {
+            parent_id: row.get(0)?,
+            area_id: row.get(1)?,
+            name: row.get(2)?,
+            hits: row.get(3)?,
+            mtime: row.get(4)?,
+        })
+    }
+}
+
+impl FromRow for DynamicInfo {
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        Ok(Self {
+            did: row.get(0)?,
+            uid: row.get(1)?,
+            dtype: row.get(2)?,
+            text: row.get(3)?,
+            images: serde_json::from_str(&row.get::<_, String>(4)?).unwrap_or_default(),
+            timestamp: row.get(5)?,
+        })
+    }
+}
+
+impl FromRow for UserStat {
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        Ok(Self {
+            uid: row.get(0)?,
+            timestamp: row.get(1)?,
+            followers: row.get(2)?,
+            videos: row.get(3)?,
+            live_viewers: row.get(4)?,
+        })
+    }
+}
+
 impl FromRow for VideoOwner {
     fn from_row(row: &Row) -> rusqlite::Result<Self> {
         Ok(Self {
@@ -133,6 +511,11 @@ impl FromRow for FilterMeta {
         Ok(Self {
             fid: row.get(0)?,
             name: row.get(1)?,
+            visit_ts: row.get(2)?,
+            counts: Default::default(),
+            query: row
+                .get::<_, Option<String>>(3)?
+                .and_then(|s| serde_json::from_str(&s).ok()),
         })
     }
 }
@@ -204,9 +587,111 @@ impl TryFrom<serde_json::Value> for VideoVector {
     }
 }
 
+pub struct DynamicVector(Vec<DynamicInfo>);
+
+impl Deref for DynamicVector {
+    type Target = Vec<DynamicInfo>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+fn str_at(v: &serde_json::Value) -> Option<String> {
+    v.as_str()
+        .filter(|s| !s.is_empty())
+        .map(ToString::to_string)
+}
+
+impl TryFrom<serde_json::Value> for DynamicVector {
+    type Error = anyhow::Error;
+    fn try_from(v: serde_json::Value) -> Result<Self, Self::Error> {
+        let mut r = Vec::new();
+        if let Some(a) = v["items"].as_array() {
+            for (i, v) in a.iter().enumerate() {
+                let author = &v["modules"]["module_author"];
+                let dynamic = &v["modules"]["module_dynamic"];
+                let major = &dynamic["major"];
+                let text: Vec<String> = [
+                    &dynamic["desc"]["text"],
+                    &major["opus"]["summary"]["text"],
+                    &major["archive"]["title"],
+                    &major["article"]["title"],
+                ]
+                .into_iter()
+                .filter_map(str_at)
+                .collect();
+                let mut images: Vec<String> = Vec::new();
+                for pics in [&major["draw"]["items"], &major["opus"]["pics"]] {
+                    if let Some(pics) = pics.as_array() {
+                        images.extend(
+                            pics.iter()
+                                .filter_map(|p| str_at(&p["src"]).or_else(|| str_at(&p["url"]))),
+                        );
+                    }
+                }
+                images.extend(str_at(&major["archive"]["cover"]));
+                if let Some(covers) = major["article"]["covers"].as_array() {
+                    images.extend(covers.iter().filter_map(str_at));
+                }
+                r.push(DynamicInfo {
+                    did: str_at(&v["id_str"])
+                        .ok_or_else(|| anyhow!("items.{}.id_str not found", i))?,
+                    uid: author["mid"]
+                        .as_i64()
+                        .ok_or_else(|| anyhow!("items.{}.module_author.mid not found", i))?,
+                    dtype: v["type"]
+                        .as_str()
+                        .map(|t| t.trim_start_matches("DYNAMIC_TYPE_").to_lowercase())
+                        .ok_or_else(|| anyhow!("items.{}.type not found", i))?,
+                    text: text.join("\n"),
+                    images,
+                    timestamp: author["pub_ts"]
+                        .as_i64()
+                        .ok_or_else(|| anyhow!("items.{}.module_author.pub_ts not found", i))?,
+                })
+            }
+        }
+        Ok(DynamicVector(r))
+    }
+}
+
+pub const DB_PATH: &str = "./.cache/cache.db3";
+
+/// Pages freed by one incremental vacuum step, the connection is released between steps.
+const VACUUM_STEP_PAGES: i64 = 100;
+
+/// Pages copied by one backup step, the database file is unlocked between steps.
+const BACKUP_STEP_PAGES: i32 = 1024;
+
+/// Lock _db_ by its lock file, held shared by processes with it opened and exclusively while
+/// it is replaced. Released when the returned file is dropped.
+fn lock_db(db: &Path, exclusive: bool) -> Result<std::fs::File> {
+    let file = std::fs::OpenOptions::new()
+        .create(true)
+        .write(true)
+        .open(db.with_extension("db3.lock"))?;
+    let locked = if exclusive {
+        fs2::FileExt::try_lock_exclusive(&file)
+    } else {
+        fs2::FileExt::try_lock_shared(&file)
+    };
+    locked.map_err(|e| anyhow!("database {} is in use: {}", db.display(), e))?;
+    Ok(file)
+}
+
 lazy_static::lazy_static! {
+    /// Shared lock of the database, for as long as the process runs.
+    static ref DB_LOCK: Result<std::fs::File> = lock_db(DB_PATH.as_ref(), false);
+
     static ref DBCON: Mutex<Option<Connection>> = {
-        let path = "./.cache/cache.db3";
+        if !cfg!(test) {
+            if let Err(e) = &*DB_LOCK {
+                log::error!("Lock database error(s): {}", e);
+                ::std::process::exit(1);
+            }
+        }
+        let path = if cfg!(test) { ":memory:" } else { DB_PATH };
         let db = match Connection::open(&path) {
             Ok(r) => r,
             Err(e) => {
@@ -215,15 +700,68 @@ lazy_static::lazy_static! {
             }
         };
         let db_init_cmds = include_str!("../assets/db_init.sql");
-        let create_result = db.execute_batch(db_init_cmds);
-        match create_result {
-            Ok(_) => log::info!("Database tables created!"),
-            Err(e) => log::warn!("Database tables creation error(s): {}", e),
+        // a failed batch would leave the connection inside its open transaction
+        if let Err(e) = db.execute_batch(db_init_cmds) {
+            log::error!("Database tables creation error(s): {}", e);
+            ::std::process::exit(1);
+        }
+        log::info!("Database tables created!");
+        if let Err(e) = db.create_collation("NAME", name_cmp) {
+            log::error!("Create collation NAME error(s): {}", e);
+        }
+        if let Err(e) = migrate(&db) {
+            log::error!("Database migration error(s): {}", e);
+            ::std::process::exit(1);
         }
         Mutex::new(Some(db))
     };
 }
 
+/// Schema changes on top of `db_init.sql`, the n-th entry upgrades `user_version` n-1 to n.
+const DB_MIGRATIONS: &[&str] = &[
+    include_str!("../assets/migrations/0001_usersync_status.sql"),
+    include_str!("../assets/migrations/0002_userinfo_history.sql"),
+    include_str!("../assets/migrations/0003_videostate.sql"),
+    include_str!("../assets/migrations/0004_filtermeta_visit.sql"),
+    include_str!("../assets/migrations/0005_refreshjob.sql"),
+    include_str!("../assets/migrations/0006_liveareahit.sql"),
+    include_str!("../assets/migrations/0007_dynamicinfo.sql"),
+    include_str!("../assets/migrations/0008_userstats.sql"),
+    include_str!("../assets/migrations/0009_usersync_order.sql"),
+    include_str!("../assets/migrations/0010_filtermeta_query.sql"),
+    include_str!("../assets/migrations/0011_usernote.sql"),
+    include_str!("../assets/migrations/0012_usersync_mute.sql"),
+    include_str!("../assets/migrations/0013_usersync_unfollow.sql"),
+    include_str!("../assets/migrations/0014_maintenancelog.sql"),
+    include_str!("../assets/migrations/0015_imagecache.sql"),
+    include_str!("../assets/migrations/0016_liveareauser.sql"),
+    include_str!("../assets/migrations/0017_imagecache_rehash.sql"),
+];
+
+/// Schema version of a fully migrated database.
+pub const SCHEMA_VERSION: i64 = DB_MIGRATIONS.len() as i64;
+
+fn migrate(db: &Connection) -> Result<()> {
+    let version: i64 = db.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+    for (i, cmds) in DB_MIGRATIONS
+        .iter()
+        .enumerate()
+        .skip(version.max(0) as usize)
+    {
+        let to = i + 1;
+        db.execute_batch(&format!(
+            "BEGIN;\n{}\nPRAGMA user_version = {};\nCOMMIT;",
+            cmds, to
+        ))
+        .map_err(|e| {
+            db.execute_batch("ROLLBACK;").ok();
+            anyhow!("migrate to version {}: {}", to, e)
+        })?;
+        log::info!("Database migrated to version {}", to);
+    }
+    Ok(())
+}
+
 macro_rules! conn_db {
     ($name:ident, $mtxdb:ident) => {
         let _guard = $mtxdb
@@ -255,92 +793,650 @@ pub fn blocking_shutdown() {
     }
 }
 
-#[derive(Debug)]
-pub enum Order {
+/// Confirmations of a missing/banned status before the user stops being auto refreshed.
+pub const SYNC_STATUS_CONFIRM_TH: i64 = 3;
+
+/// Refresh jobs are dropped after failing so many times.
+pub const JOB_MAX_ATTEMPTS: i64 = 5;
+pub const JOB_RETRY_BASE_SECS: i64 = 60;
+
+/// Images registered by pages but never fetched are forgotten after this long.
+pub const UNFETCHED_IMAGE_SECS: i64 = 30 * 24 * 3600;
+
+/// A followed user living in a live area hits it once per period.
+pub const AREA_HIT_PERIOD_SECS: i64 = 24 * 3600;
+
+/// Sort key of user lists.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OrderKey {
+    /// Latest followed in the all list, priority in a filter list.
     Rowid,
     LatestVideo,
     LiveEntropy,
+    Unseen,
+    /// Followers gained in the last 7 days.
+    Growth,
+    /// Name in [`name_cmp`] order.
+    Name,
+    /// Last refresh time.
+    Refresh,
+    Follow,
+    Unfollow,
+    /// Priority in the listed filter, or the max priority in any filter.
+    Priority,
+    LiveStart,
 }
 
-impl From<&str> for Order {
-    fn from(s: &str) -> Self {
-        match s {
+impl FromStr for OrderKey {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(match s {
+            "rowid" | "default" => Self::Rowid,
             "video" => Self::LatestVideo,
             "live" => Self::LiveEntropy,
-            _ => Self::Rowid,
-        }
+            "unseen" => Self::Unseen,
+            "growth" => Self::Growth,
+            "name" => Self::Name,
+            "refresh" => Self::Refresh,
+            "follow" => Self::Follow,
+            "unfollow" => Self::Unfollow,
+            "priority" => Self::Priority,
+            "live_start" => Self::LiveStart,
+            _ => return Err(anyhow!("unknown order `{}`", s)),
+        })
     }
 }
 
-#[derive(Clone)]
-pub struct User {
-    uid: i64,
-}
-
-type DbType<'a> = &'a rusqlite::Connection;
-
-impl User {
-    pub fn new(uid: i64) -> Self {
-        Self { uid }
+impl OrderKey {
+    /// Names sort ascending by default, all others descending.
+    fn desc_by_default(self) -> bool {
+        !matches!(self, Self::Name)
     }
 
-    pub fn info(&self) -> Result<UserInfo> {
-        conn_db!(db);
-        self.db_info(db)
+    /// Sort expression, priorities are those in filter _fid_ if given.
+    fn sql(self, fid: Option<i64>) -> String {
+        match (self, fid) {
+            (Self::Rowid, Some(fid)) | (Self::Priority, Some(fid)) => format!(
+                "(SELECT priority FROM userfilters \
+                WHERE userfilters.uid=usersync.id AND userfilters.fid={})",
+                fid
+            ),
+            (Self::Rowid, None) => "usersync.rowid".into(),
+            (Self::Priority, None) => {
+                "(SELECT MAX(priority) FROM userfilters WHERE userfilters.uid=usersync.id)".into()
+            }
+            (Self::LatestVideo, _) => "new_video_ts".into(),
+            (Self::LiveEntropy, _) => "live_entropy".into(),
+            (Self::Unseen, _) => "(SELECT COUNT(*) FROM videoowner \
+                LEFT JOIN videostate ON videostate.vid=videoowner.vid \
+                WHERE videoowner.uid=usersync.id AND IFNULL(state, 0)=0)"
+                .into(),
+            (Self::Growth, _) => "(SELECT followers FROM userstats \
+                WHERE userstats.uid=usersync.id AND followers IS NOT NULL \
+                ORDER BY timestamp DESC LIMIT 1) - \
+                (SELECT followers FROM userstats \
+                WHERE userstats.uid=usersync.id AND followers IS NOT NULL \
+                    AND timestamp>=CAST(strftime('%s', 'now') AS INTEGER)-604800 \
+                ORDER BY timestamp ASC LIMIT 1)"
+                .into(),
+            (Self::Name, _) => "userinfo.name COLLATE NAME".into(),
+            (Self::Refresh, _) => "ctimestamp".into(),
+            (Self::Follow, _) => "follow_ts".into(),
+            (Self::Unfollow, _) => "unfollow_ts".into(),
+            (Self::LiveStart, _) => "live_start_ts".into(),
+        }
     }
+}
 
-    fn db_info(&self, db: DbType) -> Result<UserInfo> {
-        Ok(db.query_row(
-            "SELECT * FROM userinfo WHERE id=?1",
-            params![self.uid],
-            UserInfo::from_row,
-        )?)
-    }
+/// Compound order of user lists, parsed from comma separated `key[:asc|:desc]` , e.g.
+/// `priority,name:asc` . Ties are broken by latest followed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Order {
+    pub keys: Vec<(OrderKey, bool)>,
+}
 
-    pub fn set_info(&self, info: &UserInfo) {
-        if info.id != self.uid {
-            log::error!("BUG: user {} set_info with info id {}", self.uid, info.id);
-            return;
+impl Order {
+    /// Order by _key_ in its default direction.
+    pub fn by(key: OrderKey) -> Self {
+        Self {
+            keys: vec![(key, key.desc_by_default())],
         }
-        conn_db!(db);
-        self.db_set_info(db, info);
     }
 
-    fn db_set_info(&self, db: DbType, info: &UserInfo) {
-        db.execute(
-            "REPLACE INTO userinfo VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
-            params![
-                info.id,
-                info.name,
-                info.face_url,
-                info.live_room_url,
-                info.live_room_title,
-                info.live_open,
-                info.live_entropy,
-            ],
+    /// Live orders list only users living now.
+    fn live_only(&self) -> bool {
+        matches!(
+            self.keys.first(),
+            Some((OrderKey::LiveEntropy, _)) | Some((OrderKey::LiveStart, _))
         )
-        .map_err(|e| log::warn!("Replace into userinfo error(s): {}", e))
-        .ok();
-        self.db_upd_ctime(db, info.id);
     }
 
-    pub fn force_upd_ctime(&self) {
-        conn_db!(db);
-        self.db_upd_ctime(db, self.id());
+    fn sql(&self, fid: Option<i64>) -> String {
+        let mut terms: Vec<String> = self
+            .keys
+            .iter()
+            .map(|(key, desc)| format!("{} {}", key.sql(fid), if *desc { "DESC" } else { "ASC" }))
+            .collect();
+        terms.push("usersync.rowid DESC".to_string());
+        terms.join(", ")
     }
+}
 
-    fn db_upd_ctime(&self, db: DbType, id: i64) {
-        let now = Utc::now();
-        db.execute(
-            "UPDATE usersync SET ctime=?2, ctimestamp=?3 WHERE id=?1",
-            params![id, now, now.timestamp(),],
-        )
-        .map_err(|e| log::warn!("Update usersync error(s): {}", e))
-        .ok();
+impl Default for Order {
+    fn default() -> Self {
+        Self::by(OrderKey::Rowid)
     }
+}
 
-    pub fn get_sync(&self) -> Result<UserSync> {
-        conn_db!(db);
+impl FromStr for Order {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let keys = s
+            .split(',')
+            .map(|term| {
+                let (key, dir) = match term.trim().split_once(':') {
+                    Some((key, dir)) => (key.parse::<OrderKey>()?, Some(dir)),
+                    None => (term.trim().parse::<OrderKey>()?, None),
+                };
+                let desc = match dir {
+                    None => key.desc_by_default(),
+                    Some("asc") => false,
+                    Some("desc") => true,
+                    Some(d) => return Err(anyhow!("unknown order direction `{}`", d)),
+                };
+                Ok((key, desc))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self { keys })
+    }
+}
+
+/// Collation `NAME` of user names: case and width insensitive, digits and latin letters
+/// before kana, kana before hangul, hangul before CJK ideographs. Within a script characters
+/// compare by code point, which for the unified ideographs block roughly follows the
+/// radical-stroke order of Unicode, but is neither pinyin nor exact across extension blocks.
+pub fn name_cmp(a: &str, b: &str) -> Ordering {
+    fn key(c: char) -> (u8, char) {
+        let c = match c as u32 {
+            // fullwidth forms of ASCII
+            0xFF01..=0xFF5E => char::from_u32(c as u32 - 0xFEE0).unwrap_or(c),
+            _ => c,
+        };
+        let c = c.to_lowercase().next().unwrap_or(c);
+        let class = match c as u32 {
+            0x30..=0x39 => 1,
+            0x61..=0x7A | 0xC0..=0x24F => 2,
+            0x3040..=0x30FF => 3,
+            0xAC00..=0xD7AF | 0x1100..=0x11FF => 4,
+            0x3400..=0x4DBF | 0x4E00..=0x9FFF | 0xF900..=0xFAFF | 0x20000..=0x2FA1F => 5,
+            _ if c.is_alphanumeric() => 6,
+            _ => 0,
+        };
+        (class, c)
+    }
+    a.chars().map(key).cmp(b.chars().map(key))
+}
+
+/// A condition on users of a [`UserQuery`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Predicate {
+    Live,
+    /// Living with at least so many viewers.
+    Viewers(i64),
+    /// Uploaded a video within the days.
+    UploadedWithin(i64),
+    /// Name containing the text, ASCII case insensitive.
+    NameContains(String),
+    /// Tagged with the tag.
+    Tag(String),
+    /// Name, note or a tag containing the text, ASCII case insensitive.
+    Text(String),
+    NeverRefreshed,
+    /// Unfollowed users, instead of followed ones.
+    Disabled,
+    /// Muted users, instead of unmuted ones.
+    Muted,
+}
+
+impl FromStr for Predicate {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        fn number(key: &str, n: &str) -> Result<i64> {
+            n.parse()
+                .map_err(|e| anyhow!("invalid number `{}` of {}: {}", n, key, e))
+        }
+        Ok(match s.split_once(':') {
+            Some(("viewers", n)) => Self::Viewers(number("viewers", n)?),
+            Some(("uploaded", days)) => Self::UploadedWithin(number("uploaded", days)?),
+            Some(("name", text)) if !text.is_empty() => Self::NameContains(text.to_string()),
+            Some(("tag", tag)) if !tag.is_empty() => Self::Tag(tag.to_string()),
+            Some(("text", text)) if !text.is_empty() => Self::Text(text.to_string()),
+            None if s == "live" => Self::Live,
+            None if s == "never_refreshed" => Self::NeverRefreshed,
+            None if s == "disabled" => Self::Disabled,
+            None if s == "muted" => Self::Muted,
+            _ => return Err(anyhow!("unknown predicate `{}`", s)),
+        })
+    }
+}
+
+impl Predicate {
+    fn sql(&self, clause: &mut Clause) -> String {
+        match self {
+            Self::Live => "live_open=1".into(),
+            Self::Viewers(n) => format!("live_open=1 AND live_entropy>={}", n),
+            Self::UploadedWithin(days) => format!(
+                "new_video_ts>=CAST(strftime('%s', 'now') AS INTEGER)-{}",
+                (*days).max(0) * 86400
+            ),
+            Self::NameContains(text) => format!(
+                "instr(lower(userinfo.name), lower({}))>0",
+                clause.bind(text.clone())
+            ),
+            Self::Tag(tag) => format!(
+                "EXISTS (SELECT 1 FROM usertag WHERE usertag.uid=usersync.id AND tag={})",
+                clause.bind(tag.clone())
+            ),
+            Self::Text(text) => {
+                let text = clause.bind(text.clone());
+                format!(
+                    "(instr(lower(userinfo.name), lower({0}))>0 \
+                    OR EXISTS (SELECT 1 FROM usernote WHERE usernote.uid=usersync.id \
+                        AND instr(lower(note), lower({0}))>0) \
+                    OR EXISTS (SELECT 1 FROM usertag WHERE usertag.uid=usersync.id \
+                        AND instr(lower(tag), lower({0}))>0))",
+                    text
+                )
+            }
+            Self::NeverRefreshed => "ctimestamp<=0".into(),
+            Self::Disabled => "enable=0".into(),
+            Self::Muted => "mute_until>CAST(strftime('%s', 'now') AS INTEGER)".into(),
+        }
+    }
+}
+
+/// Values bound by a `WHERE` clause, numbered after _first_ fixed parameters.
+struct Clause {
+    first: usize,
+    values: Vec<Value>,
+}
+
+impl Clause {
+    fn after(first: usize) -> Self {
+        Self {
+            first,
+            values: Vec::new(),
+        }
+    }
+
+    /// Bind _v_ , returns its placeholder.
+    fn bind<T: Into<Value>>(&mut self, v: T) -> String {
+        self.values.push(v.into());
+        format!("?{}", self.first + self.values.len())
+    }
+}
+
+/// Smart filters may refer to each other up to this depth.
+const SMART_FILTER_MAX_DEPTH: usize = 4;
+
+/// Textual form of a [`UserQuery`], as given in query strings and saved in smart filters.
+/// Lists are comma separated.
+#[derive(Debug, Deserialize, Serialize, Clone, Default, PartialEq, Eq, ToSchema)]
+pub struct UserQuerySpec {
+    /// Filter ids, users in any of them; all users if empty.
+    pub any: Option<String>,
+    /// Filter ids, users in all of them.
+    pub all: Option<String>,
+    /// Filter ids, users in none of them.
+    pub not: Option<String>,
+    /// Predicates: `live`, `viewers:<n>`, `uploaded:<days>`, `name:<text>`, `tag:<tag>`,
+    /// `text:<text>`, `never_refreshed`, `disabled` or `muted`.
+    pub is: Option<String>,
+    /// See [`Order`].
+    pub order: Option<String>,
+}
+
+/// Users in any of _any_ (all users if empty), in all of _all_ , in none of _not_ and
+/// satisfying all _predicates_ . Only followed and unmuted users are listed unless
+/// [`Predicate::Disabled`] or [`Predicate::Muted`] is given.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct UserQuery {
+    pub any: Vec<i64>,
+    pub all: Vec<i64>,
+    pub not: Vec<i64>,
+    pub predicates: Vec<Predicate>,
+    pub order: Order,
+}
+
+impl TryFrom<&UserQuerySpec> for UserQuery {
+    type Error = anyhow::Error;
+
+    fn try_from(spec: &UserQuerySpec) -> Result<Self> {
+        fn items<T: FromStr<Err = anyhow::Error>>(s: &Option<String>) -> Result<Vec<T>> {
+            s.as_deref()
+                .unwrap_or("")
+                .split(',')
+                .map(str::trim)
+                .filter(|item| !item.is_empty())
+                .map(str::parse)
+                .collect()
+        }
+        fn fids(s: &Option<String>) -> Result<Vec<i64>> {
+            s.as_deref()
+                .unwrap_or("")
+                .split(',')
+                .map(str::trim)
+                .filter(|item| !item.is_empty())
+                .map(|item| {
+                    item.parse()
+                        .map_err(|e| anyhow!("invalid filter id `{}`: {}", item, e))
+                })
+                .collect()
+        }
+        Ok(Self {
+            any: fids(&spec.any)?,
+            all: fids(&spec.all)?,
+            not: fids(&spec.not)?,
+            predicates: items(&spec.is)?,
+            order: match spec.order.as_deref() {
+                Some(order) => order.parse()?,
+                None => Default::default(),
+            },
+        })
+    }
+}
+
+impl UserQuery {
+    /// Members of filter _fid_ , or all users if _fid_ is not positive.
+    pub fn filter(fid: i64) -> Self {
+        Self {
+            any: if fid > 0 { vec![fid] } else { vec![] },
+            ..Default::default()
+        }
+    }
+
+    /// Query listing filter _fid_ : its saved query for a smart filter, its members otherwise.
+    pub fn of_filter(fid: i64) -> Result<Self> {
+        conn_db!(db);
+        Self::db_of_filter(db, fid)
+    }
+
+    fn db_of_filter(db: DbType, fid: i64) -> Result<Self> {
+        Ok(Self::db_smart(db, fid)?.unwrap_or_else(|| Self::filter(fid)))
+    }
+
+    /// Saved query of _fid_ if it is a smart filter.
+    fn db_smart(db: DbType, fid: i64) -> Result<Option<Self>> {
+        let spec: Option<UserQuerySpec> = db
+            .query_row(
+                "SELECT query FROM filtermeta WHERE fid=?1",
+                params![fid],
+                |row| row.get::<_, Option<String>>(0),
+            )
+            .optional()?
+            .flatten()
+            .map(|s| serde_json::from_str(&s))
+            .transpose()?;
+        spec.as_ref().map(Self::try_from).transpose()
+    }
+
+    /// Narrow this query by _other_ , whose order wins unless it is the default.
+    pub fn and(mut self, other: Self) -> Self {
+        self.any.extend(other.any);
+        self.all.extend(other.all);
+        self.not.extend(other.not);
+        self.predicates.extend(other.predicates);
+        if other.order != Order::default() {
+            self.order = other.order;
+        }
+        self
+    }
+
+    /// The filter priorities are taken from, if the query lists a single one.
+    fn priority_filter(&self) -> Option<i64> {
+        match (self.any.as_slice(), self.all.as_slice()) {
+            ([fid], []) | ([], [fid]) => Some(*fid),
+            _ => None,
+        }
+    }
+
+    /// `WHERE` clause on `usersync` left joined with `userinfo` . Being in a smart filter
+    /// is to satisfy its query.
+    fn where_sql(&self, db: DbType, clause: &mut Clause, depth: usize) -> Result<String> {
+        if depth > SMART_FILTER_MAX_DEPTH {
+            return Err(anyhow!("smart filters nested too deep"));
+        }
+        let mut member = |fid: &i64| -> Result<String> {
+            Ok(match Self::db_smart(db, *fid)? {
+                Some(q) => format!("({})", q.where_sql(db, clause, depth + 1)?),
+                None => format!(
+                    "EXISTS (SELECT 1 FROM userfilters \
+                    WHERE userfilters.uid=usersync.id AND userfilters.fid={})",
+                    fid
+                ),
+            })
+        };
+        let mut terms = Vec::new();
+        if !self.any.is_empty() {
+            let any = self
+                .any
+                .iter()
+                .map(&mut member)
+                .collect::<Result<Vec<_>>>()?;
+            terms.push(format!("({})", any.join(" OR ")));
+        }
+        for fid in &self.all {
+            terms.push(member(fid)?);
+        }
+        for fid in &self.not {
+            terms.push(format!("NOT {}", member(fid)?));
+        }
+        if !self.predicates.contains(&Predicate::Disabled) {
+            terms.push("enable=1".to_string());
+        }
+        if !self.predicates.contains(&Predicate::Muted) {
+            terms.push("mute_until<=CAST(strftime('%s', 'now') AS INTEGER)".to_string());
+        }
+        terms.extend(self.predicates.iter().map(|p| p.sql(clause)));
+        if self.order.live_only() {
+            terms.push(Predicate::Live.sql(clause));
+        }
+        Ok(terms.join(" AND "))
+    }
+
+    pub fn list(&self, start: i64, len: i64) -> Result<Vec<i64>> {
+        conn_db!(db);
+        self.db_list(db, start, len)
+    }
+
+    fn db_list(&self, db: DbType, start: i64, len: i64) -> Result<Vec<i64>> {
+        let mut clause = Clause::after(2);
+        let sql = format!(
+            "SELECT usersync.id FROM usersync \
+            LEFT JOIN userinfo ON userinfo.id=usersync.id \
+            WHERE {} ORDER BY {} LIMIT ?2 OFFSET ?1",
+            self.where_sql(db, &mut clause, 0)?,
+            self.order.sql(self.priority_filter()),
+        );
+        let mut stmt = db.prepare_cached(&sql)?;
+        let values = vec![Value::from(start), Value::from(len)]
+            .into_iter()
+            .chain(clause.values);
+        let iter = stmt.query_map(params_from_iter(values), |row| row.get(0))?;
+        Ok(iter.filter_map(|id| id.ok()).collect())
+    }
+}
+
+#[derive(Clone)]
+pub struct User {
+    uid: i64,
+}
+
+type DbType<'a> = &'a rusqlite::Connection;
+
+/// Live room url without query, profile and live list give different queries of one room.
+fn live_room_of(url: &Option<String>) -> Option<&str> {
+    url.as_deref().and_then(|u| u.split('?').next())
+}
+
+impl User {
+    pub fn new(uid: i64) -> Self {
+        Self { uid }
+    }
+
+    pub fn info(&self) -> Result<UserInfo> {
+        conn_db!(db);
+        self.db_info(db)
+    }
+
+    fn db_info(&self, db: DbType) -> Result<UserInfo> {
+        Ok(db.query_row(
+            "SELECT * FROM userinfo WHERE id=?1",
+            params![self.uid],
+            UserInfo::from_row,
+        )?)
+    }
+
+    pub fn set_info(&self, info: &UserInfo) {
+        if info.id != self.uid {
+            log::error!("BUG: user {} set_info with info id {}", self.uid, info.id);
+            return;
+        }
+        conn_db!(db);
+        self.db_set_info(db, info);
+    }
+
+    fn db_set_info(&self, db: DbType, info: &UserInfo) {
+        self.db_record_history(db, info);
+        self.db_upd_live_start(db, info);
+        db.execute(
+            "REPLACE INTO userinfo VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![
+                info.id,
+                info.name,
+                info.face_url,
+                info.live_room_url,
+                info.live_room_title,
+                info.live_open,
+                info.live_entropy,
+            ],
+        )
+        .map_err(|e| log::warn!("Replace into userinfo error(s): {}", e))
+        .ok();
+        self.db_upd_ctime(db, info.id);
+    }
+
+    /// Update the live room columns only, as polled from live statuses, which unlike
+    /// [`Self::set_info`] is no refresh of the user and leaves its ctime alone.
+    pub fn set_live(&self, info: &UserInfo) {
+        if info.id != self.uid {
+            log::error!("BUG: user {} set_live with info id {}", self.uid, info.id);
+            return;
+        }
+        conn_db!(db);
+        self.db_set_live(db, info);
+    }
+
+    fn db_set_live(&self, db: DbType, info: &UserInfo) {
+        self.db_record_history(db, info);
+        self.db_upd_live_start(db, info);
+        db.execute(
+            "UPDATE userinfo SET live_room_url=?2, live_room_title=?3, live_open=?4, \
+            live_entropy=?5 WHERE id=?1",
+            params![
+                info.id,
+                info.live_room_url,
+                info.live_room_title,
+                info.live_open,
+                info.live_entropy,
+            ],
+        )
+        .map_err(|e| log::warn!("Update userinfo live error(s): {}", e))
+        .ok();
+    }
+
+    /// Record the live start when _info_ opens a live not seen open before.
+    fn db_upd_live_start(&self, db: DbType, info: &UserInfo) {
+        if info.live_open != Some(true) {
+            return;
+        }
+        db.execute(
+            "UPDATE usersync SET live_start_ts=?2 WHERE id=?1 AND NOT EXISTS \
+            (SELECT 1 FROM userinfo WHERE id=?1 AND live_open=1)",
+            params![info.id, Utc::now().timestamp()],
+        )
+        .map_err(|e| log::warn!("Update usersync live start error(s): {}", e))
+        .ok();
+    }
+
+    fn db_record_history(&self, db: DbType, info: &UserInfo) {
+        let last = db
+            .query_row(
+                "SELECT * FROM userinfo_history WHERE id=?1 \
+                ORDER BY timestamp DESC, rowid DESC LIMIT 1",
+                params![self.uid],
+                UserInfoHistory::from_row,
+            )
+            .optional()
+            .map_err(|e| log::warn!("Select userinfo_history error(s): {}", e))
+            .ok();
+        let changed = match last {
+            Some(Some(h)) => {
+                h.name != info.name
+                    || h.face_url != info.face_url
+                    || (info.live_room_url.is_some()
+                        && live_room_of(&h.live_room_url) != live_room_of(&info.live_room_url))
+            }
+            Some(None) => true,
+            None => false,
+        };
+        if changed {
+            db.execute(
+                "INSERT INTO userinfo_history VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![
+                    info.id,
+                    info.name,
+                    info.face_url,
+                    info.live_room_url,
+                    Utc::now().timestamp(),
+                ],
+            )
+            .map_err(|e| log::warn!("Insert into userinfo_history error(s): {}", e))
+            .ok();
+        }
+    }
+
+    /// Recorded profiles of the user, latest first.
+    pub fn history(&self) -> Result<Vec<UserInfoHistory>> {
+        conn_db!(db);
+        let mut stmt = db.prepare_cached(
+            "SELECT * FROM userinfo_history WHERE id=?1 \
+            ORDER BY timestamp DESC, rowid DESC",
+        )?;
+        let iter = stmt.query_map(params![self.uid], UserInfoHistory::from_row)?;
+        Ok(iter.filter_map(|o| o.ok()).collect())
+    }
+
+    pub fn force_upd_ctime(&self) {
+        conn_db!(db);
+        self.db_upd_ctime(db, self.id());
+    }
+
+    fn db_upd_ctime(&self, db: DbType, id: i64) {
+        let now = Utc::now();
+        db.execute(
+            "UPDATE usersync SET ctime=?2, ctimestamp=?3 WHERE id=?1",
+            params![id, now, now.timestamp(),],
+        )
+        .map_err(|e| log::warn!("Update usersync error(s): {}", e))
+        .ok();
+    }
+
+    pub fn get_sync(&self) -> Result<UserSync> {
+        conn_db!(db);
         self.db_get_sync(db)
     }
 
@@ -387,6 +1483,54 @@ impl User {
             .collect())
     }
 
+    /// All stored videos of the user, latest first.
+    pub fn videos(&self, start: i64, len: i64) -> Result<Vec<StatedVideo>> {
+        conn_db!(db);
+        let mut stmt = db.prepare_cached(
+            "SELECT videoinfo.*, videoowner.uid, videostate.state FROM videoowner \
+            INNER JOIN videoinfo ON videoinfo.vid=videoowner.vid \
+            LEFT JOIN videostate ON videostate.vid=videoowner.vid \
+            WHERE uid=?1 \
+            ORDER BY timestamp DESC LIMIT ?3 OFFSET ?2",
+        )?;
+        let iter = stmt.query_map(params![self.uid, start, len], StatedVideo::from_row)?;
+        Ok(iter.filter_map(|o| o.ok()).collect())
+    }
+
+    pub fn unseen_count(&self) -> Result<i64> {
+        conn_db!(db);
+        Ok(db.query_row(
+            "SELECT COUNT(*) FROM videoowner \
+            LEFT JOIN videostate ON videostate.vid=videoowner.vid \
+            WHERE uid=?1 AND IFNULL(state, 0)=0",
+            params![self.uid],
+            |row| row.get(0),
+        )?)
+    }
+
+    /// Change state of all the user's videos which are in state _from_ .
+    pub fn mark_videos(&self, from: VideoState, to: VideoState) -> Result<()> {
+        conn_db!(db);
+        let vids: Vec<String> = {
+            let mut stmt = db.prepare_cached(
+                "SELECT videoowner.vid FROM videoowner \
+                LEFT JOIN videostate ON videostate.vid=videoowner.vid \
+                WHERE uid=?1 AND IFNULL(state, 0)=?2",
+            )?;
+            let iter = stmt.query_map(params![self.uid, i64::from(from)], |row| row.get(0))?;
+            iter.filter_map(|o| o.ok()).collect()
+        };
+        VideoInfo::db_mark(db, &vids, to)
+    }
+
+    /// Filters the user joined in.
+    pub fn filters(&self) -> Result<Vec<UserFilter>> {
+        conn_db!(db);
+        let mut stmt = db.prepare_cached("SELECT * FROM userfilters WHERE uid=?1")?;
+        let iter = stmt.query_map(params![self.uid], UserFilter::from_row)?;
+        Ok(iter.filter_map(|o| o.ok()).collect())
+    }
+
     pub fn update_videos<'a>(&self, videos: impl Iterator<Item = &'a VideoInfo>) {
         conn_db!(db);
         for v in videos {
@@ -417,76 +1561,328 @@ impl User {
         .ok();
     }
 
-    pub fn id(&self) -> i64 {
-        self.uid
-    }
-
-    pub fn oldest_ctime_user() -> Result<Self> {
+    /// Save dynamics of this user, returns those not saved before.
+    pub fn update_dynamics<'a>(
+        &self,
+        dynamics: impl Iterator<Item = &'a DynamicInfo>,
+    ) -> Vec<DynamicInfo> {
         conn_db!(db);
-        let uid = Self::db_oldest_ctime_user(db)?;
-        Ok(Self { uid })
+        dynamics
+            .filter(|d| d.uid == self.uid)
+            .filter(|d| {
+                db.execute(
+                    "INSERT OR IGNORE INTO dynamicinfo VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                    params![
+                        d.did,
+                        d.uid,
+                        d.dtype,
+                        d.text,
+                        serde_json::to_string(&d.images).unwrap_or_default(),
+                        d.timestamp
+                    ],
+                )
+                .map_err(|e| log::warn!("Insert or ignore into dynamicinfo error(s): {}", e))
+                .map(|n| n > 0)
+                .unwrap_or(false)
+            })
+            .cloned()
+            .collect()
     }
 
-    fn db_oldest_ctime_user(db: DbType) -> Result<i64> {
-        Ok(db.query_row(
-            "SELECT id FROM usersync WHERE enable=1 ORDER BY ctimestamp ASC LIMIT 1",
-            [],
-            |row| row.get(0),
-        )?)
+    pub fn record_stat(
+        &self,
+        followers: Option<i64>,
+        videos: Option<i64>,
+        live_viewers: Option<i64>,
+    ) {
+        conn_db!(db);
+        db.execute(
+            "REPLACE INTO userstats VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                self.uid,
+                Utc::now().timestamp(),
+                followers,
+                videos,
+                live_viewers
+            ],
+        )
+        .map_err(|e| log::warn!("Replace into userstats error(s): {}", e))
+        .ok();
     }
 
-    pub fn enable(&self, b: bool) {
+    pub fn note(&self) -> Result<UserNote> {
         conn_db!(db);
-        self.db_disable(db, b);
+        self.db_note(db)
     }
 
-    fn db_disable(&self, db: DbType, b: bool) {
-        let z = DateTime::<Utc>::MIN_UTC;
-        db.execute(
-            "REPLACE INTO usersync VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
-            params![self.uid, b, z, z.timestamp(), z.timestamp(), ""],
-        )
-        .map_err(|e| {
-            log::error!(
-                "Update usersync uid {} enable flag {} error(s): {}",
-                self.uid,
-                b,
-                e
+    fn db_note(&self, db: DbType) -> Result<UserNote> {
+        let (note, mtime) = db
+            .query_row(
+                "SELECT note, mtime FROM usernote WHERE uid=?1",
+                params![self.uid],
+                |row| Ok((row.get(0)?, row.get(1)?)),
             )
+            .optional()?
+            .unwrap_or_default();
+        let mut stmt = db.prepare_cached("SELECT tag FROM usertag WHERE uid=?1 ORDER BY rowid")?;
+        let tags = stmt
+            .query_map(params![self.uid], |row| row.get(0))?
+            .filter_map(|o| o.ok())
+            .collect();
+        Ok(UserNote {
+            uid: self.uid,
+            note,
+            tags,
+            mtime,
         })
-        .ok();
     }
 
-    pub fn list(fid: i64, order: Order, start: i64, len: i64) -> Result<Vec<i64>> {
+    /// Replace note and tags of this user. Tags are trimmed, deduplicated and empty ones
+    /// dropped.
+    pub fn set_note(&self, note: &str, tags: &[String]) -> Result<UserNote> {
         conn_db!(db);
-        if fid <= 0 {
-            Self::db_list(db, order, start, len)
-        } else {
-            Self::db_filter_list(db, fid, order, start, len)
-        }
+        let tx = db.unchecked_transaction()?;
+        self.db_set_note(&tx, note, tags, Utc::now().timestamp())?;
+        tx.commit()?;
+        self.db_note(db)
     }
 
-    fn db_list(db: DbType, order: Order, start: i64, len: i64) -> Result<Vec<i64>> {
-        let mut stmt = db.prepare_cached(match order {
-            Order::Rowid => {
-                "SELECT id FROM usersync \
-                    WHERE enable=1 \
-                    ORDER BY rowid DESC LIMIT ?2 OFFSET ?1"
-            }
-            Order::LatestVideo => {
-                "SELECT id FROM usersync \
-                    WHERE enable=1 \
-                    ORDER BY new_video_ts DESC LIMIT ?2 OFFSET ?1"
-            }
-            Order::LiveEntropy => {
-                "SELECT userinfo.id FROM userinfo \
-                    INNER JOIN usersync ON usersync.id=userinfo.id \
-                    WHERE live_open=1 and enable=1 \
-                    ORDER BY live_entropy DESC LIMIT ?2 OFFSET ?1"
-            }
+    fn db_set_note(&self, db: DbType, note: &str, tags: &[String], mtime: i64) -> Result<()> {
+        db.execute(
+            "REPLACE INTO usernote VALUES (?1, ?2, ?3)",
+            params![self.uid, note.trim(), mtime],
+        )?;
+        db.execute("DELETE FROM usertag WHERE uid=?1", params![self.uid])?;
+        for tag in tags.iter().map(|t| t.trim()).filter(|t| !t.is_empty()) {
+            db.execute(
+                "INSERT OR IGNORE INTO usertag VALUES (?1, ?2)",
+                params![self.uid, tag],
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Stats samples of this user since _since_ , oldest first.
+    pub fn stats(&self, since: i64) -> Result<Vec<UserStat>> {
+        conn_db!(db);
+        let mut stmt = db.prepare_cached(
+            "SELECT * FROM userstats WHERE uid=?1 AND timestamp>=?2 ORDER BY timestamp",
+        )?;
+        let iter = stmt.query_map(params![self.uid, since], UserStat::from_row)?;
+        Ok(iter.filter_map(|o| o.ok()).collect())
+    }
+
+    /// Dynamics of this user, latest first.
+    pub fn dynamics(&self, start: i64, len: i64) -> Result<Vec<DynamicInfo>> {
+        conn_db!(db);
+        let mut stmt = db.prepare_cached(
+            "SELECT * FROM dynamicinfo WHERE uid=?1 \
+            ORDER BY timestamp DESC LIMIT ?3 OFFSET ?2",
+        )?;
+        let iter = stmt.query_map(params![self.uid, start, len], DynamicInfo::from_row)?;
+        Ok(iter.filter_map(|o| o.ok()).collect())
+    }
+
+    pub fn id(&self) -> i64 {
+        self.uid
+    }
+
+    pub fn oldest_ctime_user() -> Result<Self> {
+        conn_db!(db);
+        let uid = Self::db_oldest_ctime_user(db)?;
+        Ok(Self { uid })
+    }
+
+    /// Followed users with a known live room, paired with whether they are in filter _fid_ .
+    pub fn live_room_users(fid: i64) -> Result<Vec<(i64, bool)>> {
+        conn_db!(db);
+        let mut stmt = db.prepare_cached(
+            "SELECT userinfo.id, EXISTS(SELECT 1 FROM userfilters \
+                WHERE userfilters.uid=userinfo.id AND userfilters.fid=?1) \
+            FROM userinfo INNER JOIN usersync ON usersync.id=userinfo.id \
+            WHERE enable=1 AND (status=0 OR status_cnt<?2) AND live_room_url IS NOT NULL \
+                AND NOT (mute_pause=1 AND mute_until>?3) \
+            ORDER BY userinfo.id",
+        )?;
+        let now = Utc::now().timestamp();
+        let iter = stmt.query_map(params![fid, SYNC_STATUS_CONFIRM_TH, now], |row| {
+            Ok((row.get(0)?, row.get(1)?))
         })?;
-        let iter = stmt.query_map(params![start, len], |row| row.get(0))?;
-        Ok(iter.filter_map(|id| id.ok()).collect())
+        Ok(iter.filter_map(|o| o.ok()).collect())
+    }
+
+    fn db_oldest_ctime_user(db: DbType) -> Result<i64> {
+        Ok(db.query_row(
+            "SELECT id FROM usersync \
+            WHERE enable=1 AND (status=0 OR status_cnt<?1) \
+                AND NOT (mute_pause=1 AND mute_until>?2) \
+            ORDER BY ctimestamp ASC LIMIT 1",
+            params![SYNC_STATUS_CONFIRM_TH, Utc::now().timestamp()],
+            |row| row.get(0),
+        )?)
+    }
+
+    /// Mute this user until _until_ : hidden from lists and notifications, and with _pause_
+    /// not auto refreshed either. An _until_ not in future unmutes.
+    pub fn mute(&self, until: i64, pause: bool) -> Result<()> {
+        conn_db!(db);
+        let n = db.execute(
+            "UPDATE usersync SET mute_until=?2, mute_pause=?3 WHERE id=?1",
+            params![self.uid, until, pause],
+        )?;
+        if n == 0 {
+            return Err(anyhow!("user {} is not followed", self.uid));
+        }
+        Ok(())
+    }
+
+    pub fn muted(&self) -> bool {
+        self.get_sync()
+            .map(|s| s.mute_until > Utc::now().timestamp())
+            .unwrap_or(false)
+    }
+
+    /// Record the account status confirmed by a refresh. Repeating a non-active status counts
+    /// up confirmations, the user stops being scheduled at [`SYNC_STATUS_CONFIRM_TH`].
+    pub fn set_status(&self, status: SyncStatus) {
+        conn_db!(db);
+        self.db_set_status(db, status);
+    }
+
+    fn db_set_status(&self, db: DbType, status: SyncStatus) {
+        let r = if status == SyncStatus::Active {
+            db.execute(
+                "UPDATE usersync SET status=0, status_ts=?2, status_cnt=0 \
+                WHERE id=?1 AND status!=0",
+                params![self.uid, Utc::now().timestamp()],
+            )
+        } else {
+            db.execute(
+                "UPDATE usersync SET \
+                status_cnt=CASE WHEN status=?2 THEN status_cnt+1 ELSE 1 END, \
+                status_ts=CASE WHEN status=?2 THEN status_ts ELSE ?3 END, \
+                status=?2 \
+                WHERE id=?1",
+                params![self.uid, i64::from(status), Utc::now().timestamp()],
+            )
+        };
+        r.map_err(|e| log::warn!("Update usersync status error(s): {}", e))
+            .ok();
+    }
+
+    pub fn enable(&self, b: bool) {
+        conn_db!(db);
+        self.db_disable(db, b);
+    }
+
+    /// Follow or unfollow, keeping the sync state, videos and filters of the user.
+    fn db_disable(&self, db: DbType, b: bool) {
+        let z = DateTime::<Utc>::MIN_UTC;
+        let now = Utc::now().timestamp();
+        db.execute(
+            "INSERT OR IGNORE INTO usersync \
+            (id, enable, ctime, ctimestamp, new_video_ts, new_video_title) \
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![self.uid, b, z, z.timestamp(), z.timestamp(), ""],
+        )
+        .and_then(|_| {
+            db.execute(
+                if b {
+                    "UPDATE usersync SET \
+                    follow_ts=CASE WHEN enable=0 OR follow_ts=0 THEN ?2 ELSE follow_ts END, \
+                    enable=1 WHERE id=?1"
+                } else {
+                    "UPDATE usersync SET \
+                    unfollow_ts=CASE WHEN enable=1 OR unfollow_ts=0 THEN ?2 ELSE unfollow_ts END, \
+                    enable=0 WHERE id=?1"
+                },
+                params![self.uid, now],
+            )
+        })
+        .map_err(|e| {
+            log::error!(
+                "Update usersync uid {} enable flag {} error(s): {}",
+                self.uid,
+                b,
+                e
+            )
+        })
+        .ok();
+    }
+
+    /// Remote images of this user: avatars, covers of videos only owned by it and images of
+    /// dynamics.
+    fn db_image_urls(&self, db: DbType) -> Result<Vec<String>> {
+        let mut urls = vec![];
+        let mut stmt = db.prepare(
+            "SELECT face_url FROM userinfo WHERE id=?1 \
+            UNION SELECT face_url FROM userinfo_history WHERE id=?1 \
+            UNION SELECT pic_url FROM videoinfo WHERE vid IN \
+                (SELECT vid FROM videoowner WHERE uid=?1) \
+                AND vid NOT IN (SELECT vid FROM videoowner WHERE uid<>?1)",
+        )?;
+        let iter = stmt.query_map(params![self.uid], |row| row.get(0))?;
+        urls.extend(iter.filter_map(|o| o.ok()));
+        let mut stmt = db.prepare("SELECT images FROM dynamicinfo WHERE uid=?1")?;
+        let iter = stmt.query_map(params![self.uid], |row| row.get::<_, String>(0))?;
+        for images in iter.filter_map(|o| o.ok()) {
+            urls.extend(serde_json::from_str::<Vec<String>>(&images).unwrap_or_default());
+        }
+        urls.retain(|u| !u.is_empty());
+        Ok(urls)
+    }
+
+    /// Remove everything recorded of this unfollowed user: sync state, profiles, videos only
+    /// owned by it, filters, dynamics, stats, notes, jobs, live areas and cached images.
+    /// Returns count of deleted rows.
+    pub fn purge(&self) -> Result<usize> {
+        conn_db!(db);
+        if self.db_get_sync(db)?.enable {
+            return Err(anyhow!("user {} is followed, unfollow first", self.uid));
+        }
+        let tx = db.unchecked_transaction()?;
+        let mut n = 0;
+        for url in self.db_image_urls(&tx)? {
+            // cached urls carry a scheme and a resize suffix
+            let url = url.split_once("//").map(|(_, u)| u).unwrap_or(&url);
+            n += tx.execute(
+                "DELETE FROM imagecache WHERE substr(url, instr(url, '//') + 2, length(?1))=?1",
+                params![url],
+            )?;
+        }
+        for sql in [
+            "DELETE FROM videostate WHERE vid IN (SELECT vid FROM videoowner WHERE uid=?1) \
+                AND vid NOT IN (SELECT vid FROM videoowner WHERE uid<>?1)",
+            "DELETE FROM videoinfo WHERE vid IN (SELECT vid FROM videoowner WHERE uid=?1) \
+                AND vid NOT IN (SELECT vid FROM videoowner WHERE uid<>?1)",
+            "DELETE FROM videoowner WHERE uid=?1",
+            "DELETE FROM userfilters WHERE uid=?1",
+            "DELETE FROM dynamicinfo WHERE uid=?1",
+            "DELETE FROM userstats WHERE uid=?1",
+            "DELETE FROM usernote WHERE uid=?1",
+            "DELETE FROM usertag WHERE uid=?1",
+            "DELETE FROM refreshjob WHERE uid=?1",
+            "DELETE FROM liveareauser WHERE uid=?1",
+            "DELETE FROM userinfo_history WHERE id=?1",
+            "DELETE FROM userinfo WHERE id=?1",
+            "DELETE FROM usersync WHERE id=?1",
+        ] {
+            n += tx.execute(sql, params![self.uid])?;
+        }
+        tx.commit()?;
+        log::info!("Purged uid {}, {} rows deleted", self.uid, n);
+        Ok(n)
+    }
+
+    /// Users listed in filter _fid_ in _order_ , a smart filter keeps its saved order for
+    /// the default one.
+    pub fn list(fid: i64, order: Order, start: i64, len: i64) -> Result<Vec<i64>> {
+        conn_db!(db);
+        let query = UserQuery::db_of_filter(db, fid)?.and(UserQuery {
+            order,
+            ..Default::default()
+        });
+        query.db_list(db, start, len)
     }
 
     /// Modify user's priority in filter _fid_ . Priority of non-positive is equal to delete.
@@ -515,48 +1911,595 @@ impl User {
 
     pub fn filter_list(fid: i64, start: i64, len: i64) -> Result<Vec<i64>> {
         conn_db!(db);
-        Self::db_filter_list(db, fid, Order::Rowid, start, len)
+        UserQuery::filter(fid).db_list(db, start, len)
     }
+}
 
-    fn db_filter_list(
-        db: DbType,
-        fid: i64,
-        order: Order,
-        start: i64,
-        len: i64,
-    ) -> Result<Vec<i64>> {
-        let mut stmt = db.prepare_cached(match order {
-            Order::Rowid => {
-                "SELECT id FROM usersync \
-                    INNER JOIN userfilters ON userfilters.uid=usersync.id and fid=?1 \
-                    WHERE enable=1 \
-                    ORDER BY priority DESC LIMIT ?3 OFFSET ?2"
+impl VideoInfo {
+    pub fn mark<T: AsRef<str>>(vids: &[T], state: VideoState) -> Result<()> {
+        conn_db!(db);
+        Self::db_mark(db, vids, state)
+    }
+
+    fn db_mark<T: AsRef<str>>(db: DbType, vids: &[T], state: VideoState) -> Result<()> {
+        let now = Utc::now().timestamp();
+        for vid in vids {
+            if state == VideoState::Unseen {
+                db.execute("DELETE FROM videostate WHERE vid=?1", params![vid.as_ref()])?;
+            } else {
+                db.execute(
+                    "REPLACE INTO videostate VALUES (?1, ?2, ?3)",
+                    params![vid.as_ref(), i64::from(state), now],
+                )?;
             }
-            Order::LatestVideo => {
-                "SELECT id FROM usersync \
-                    INNER JOIN userfilters ON userfilters.uid=usersync.id and fid=?1 \
-                    WHERE enable=1 \
-                    ORDER BY new_video_ts DESC LIMIT ?3 OFFSET ?2"
+        }
+        Ok(())
+    }
+
+    /// Videos of all users in _state_ , latest marked first.
+    pub fn list_state(state: VideoState, start: i64, len: i64) -> Result<Vec<StatedVideo>> {
+        conn_db!(db);
+        let mut stmt = db.prepare_cached(
+            "SELECT videoinfo.*, videoowner.uid, videostate.state FROM videostate \
+            INNER JOIN videoinfo ON videoinfo.vid=videostate.vid \
+            INNER JOIN videoowner ON videoowner.vid=videostate.vid \
+            WHERE state=?1 \
+            ORDER BY mtime DESC LIMIT ?3 OFFSET ?2",
+        )?;
+        let iter = stmt.query_map(params![i64::from(state), start, len], StatedVideo::from_row)?;
+        Ok(iter.filter_map(|o| o.ok()).collect())
+    }
+}
+
+impl RefreshJob {
+    /// Queue refresh of _uid_ , or make the existing job of it due now with the higher priority.
+    pub fn push(uid: i64, kind: JobKind) -> Result<()> {
+        conn_db!(db);
+        let now = Utc::now().timestamp();
+        db.execute(
+            "INSERT OR IGNORE INTO refreshjob (uid, kind, priority, not_before, ctime) \
+            VALUES (?1, ?2, ?3, ?4, ?4)",
+            params![uid, i64::from(kind), kind.priority(), now],
+        )?;
+        db.execute(
+            "UPDATE refreshjob SET priority=MAX(priority, ?2), not_before=MIN(not_before, ?3) \
+            WHERE uid=?1",
+            params![uid, kind.priority(), now],
+        )?;
+        Ok(())
+    }
+
+    /// The most urgent job which is due.
+    pub fn next() -> Result<Option<Self>> {
+        conn_db!(db);
+        Ok(db
+            .query_row(
+                "SELECT * FROM refreshjob WHERE not_before<=?1 \
+                ORDER BY priority DESC, not_before, ctime LIMIT 1",
+                params![Utc::now().timestamp()],
+                Self::from_row,
+            )
+            .optional()?)
+    }
+
+    pub fn all() -> Result<Vec<Self>> {
+        conn_db!(db);
+        let mut stmt = db
+            .prepare_cached("SELECT * FROM refreshjob ORDER BY priority DESC, not_before, ctime")?;
+        let iter = stmt.query_map([], Self::from_row)?;
+        Ok(iter.filter_map(|o| o.ok()).collect())
+    }
+
+    /// Remove the job of _uid_ , false if there is none.
+    pub fn remove(uid: i64) -> Result<bool> {
+        conn_db!(db);
+        Ok(db.execute("DELETE FROM refreshjob WHERE uid=?1", params![uid])? > 0)
+    }
+
+    /// Record a failed attempt of the job of _uid_ , it is retried with exponential backoff or
+    /// dropped once reached `JOB_MAX_ATTEMPTS`.
+    pub fn failed<T: ToString>(uid: i64, why: T) -> Result<()> {
+        conn_db!(db);
+        let attempts: i64 = match db
+            .query_row(
+                "SELECT attempts FROM refreshjob WHERE uid=?1",
+                params![uid],
+                |row| row.get(0),
+            )
+            .optional()?
+        {
+            Some(n) => n + 1,
+            None => return Ok(()),
+        };
+        if attempts >= JOB_MAX_ATTEMPTS {
+            log::warn!("Drop refresh job uid {} after {} attempts", uid, attempts);
+            db.execute("DELETE FROM refreshjob WHERE uid=?1", params![uid])?;
+        } else {
+            let retry = Utc::now().timestamp() + (JOB_RETRY_BASE_SECS << attempts);
+            db.execute(
+                "UPDATE refreshjob SET attempts=?2, last_error=?3, not_before=?4 WHERE uid=?1",
+                params![uid, attempts, why.to_string(), retry],
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl DynamicInfo {
+    /// Dynamics of followed users in filter _fid_ (all for 0), latest first.
+    pub fn timeline(fid: i64, start: i64, len: i64) -> Result<Vec<Self>> {
+        conn_db!(db);
+        let mut stmt = db.prepare_cached(
+            "SELECT dynamicinfo.* FROM dynamicinfo \
+            INNER JOIN usersync ON usersync.id=dynamicinfo.uid \
+            WHERE usersync.enable=1 AND mute_until<=?4 \
+                AND (?1<=0 OR EXISTS(SELECT 1 FROM userfilters \
+                WHERE userfilters.uid=dynamicinfo.uid AND userfilters.fid=?1)) \
+            ORDER BY timestamp DESC LIMIT ?3 OFFSET ?2",
+        )?;
+        let now = Utc::now().timestamp();
+        let iter = stmt.query_map(params![fid, start, len, now], Self::from_row)?;
+        Ok(iter.filter_map(|o| o.ok()).collect())
+    }
+}
+
+impl UserNote {
+    /// Notes of all annotated users.
+    pub fn all() -> Result<Vec<Self>> {
+        conn_db!(db);
+        let mut stmt = db.prepare_cached(
+            "SELECT uid FROM usernote UNION SELECT uid FROM usertag ORDER BY uid",
+        )?;
+        let uids: Vec<i64> = stmt
+            .query_map([], |row| row.get(0))?
+            .filter_map(|o| o.ok())
+            .collect();
+        uids.into_iter()
+            .map(|uid| User::new(uid).db_note(db))
+            .collect()
+    }
+
+    /// Replace notes and tags of the users in _notes_ , as exported by [`UserNote::all`] , all
+    /// or none. Users not in _notes_ are left alone, a zero `mtime` is taken as now. Returns the
+    /// stored notes.
+    pub fn import(notes: &[UserNote]) -> Result<Vec<Self>> {
+        conn_db!(db);
+        let now = Utc::now().timestamp();
+        let tx = db.unchecked_transaction()?;
+        for n in notes {
+            let mtime = if n.mtime > 0 { n.mtime } else { now };
+            User::new(n.uid).db_set_note(&tx, &n.note, &n.tags, mtime)?;
+        }
+        tx.commit()?;
+        notes.iter().map(|n| User::new(n.uid).db_note(db)).collect()
+    }
+
+    /// All tags in use with their user counts, most used first.
+    pub fn tags() -> Result<Vec<(String, i64)>> {
+        conn_db!(db);
+        let mut stmt = db.prepare_cached(
+            "SELECT tag, COUNT(*) AS n FROM usertag GROUP BY tag ORDER BY n DESC, tag",
+        )?;
+        let iter = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        Ok(iter.filter_map(|o| o.ok()).collect())
+    }
+}
+
+impl UserStat {
+    /// Keep only the last sample of each day for samples older than _raw_secs_ , and drop
+    /// samples older than _keep_secs_ . Returns count of deleted samples.
+    pub fn compact(raw_secs: i64, keep_secs: i64) -> Result<usize> {
+        conn_db!(db);
+        let now = Utc::now().timestamp();
+        let downsampled = db.execute(
+            "DELETE FROM userstats WHERE timestamp<?1 AND rowid NOT IN \
+            (SELECT MAX(rowid) FROM userstats WHERE timestamp<?1 \
+                GROUP BY uid, timestamp/86400)",
+            params![now - raw_secs],
+        )?;
+        let expired = db.execute(
+            "DELETE FROM userstats WHERE timestamp<?1",
+            params![now - keep_secs],
+        )?;
+        Ok(downsampled + expired)
+    }
+}
+
+impl Maintenance {
+    /// Prune by the retention settings, check integrity, optimize and vacuum incrementally.
+    /// The report is logged into database as well.
+    pub fn run(&self) -> Result<MaintenanceReport> {
+        let t = std::time::Instant::now();
+        let mut report = MaintenanceReport {
+            started: Utc::now().timestamp(),
+            ..Default::default()
+        };
+        if self.unfollowed_secs > 0 {
+            for uid in self.expired_unfollowed(report.started)? {
+                User::new(uid).purge()?;
+                report.purged_users.push(uid);
             }
-            Order::LiveEntropy => {
-                "SELECT usersync.id FROM usersync \
-                    INNER JOIN userfilters ON userfilters.uid=usersync.id and fid=?1 \
-                    INNER JOIN userinfo ON userinfo.id=usersync.id \
-                    WHERE live_open=1 and enable=1 \
-                    ORDER BY live_entropy DESC LIMIT ?3 OFFSET ?2"
+        }
+        // Every step takes the connection on its own, leaving room for others in between.
+        if self.keep_videos > 0 {
+            conn_db!(db);
+            report.pruned_videos = db.execute(
+                "DELETE FROM videoowner WHERE rowid IN (SELECT o.rowid FROM videoowner o \
+                LEFT JOIN videostate ON videostate.vid=o.vid \
+                WHERE IFNULL(state, 0)<>?2 AND (SELECT COUNT(*) FROM videoowner i \
+                    WHERE i.uid=o.uid AND i.timestamp>o.timestamp)>=?1)",
+                params![self.keep_videos, i64::from(VideoState::Saved)],
+            )?;
+        }
+        report.orphan_rows = {
+            conn_db!(db);
+            db.execute(
+                "DELETE FROM videoinfo WHERE vid NOT IN (SELECT vid FROM videoowner)",
+                [],
+            )? + db.execute(
+                "DELETE FROM videostate WHERE vid NOT IN (SELECT vid FROM videoowner)",
+                [],
+            )?
+        };
+        report.pruned_images = {
+            conn_db!(db);
+            db.execute(
+                "DELETE FROM imagecache WHERE bytes=0 AND atime<?1",
+                params![report.started - UNFETCHED_IMAGE_SECS],
+            )?
+        };
+        if let Some(dir) = &self.image_dir {
+            report.pruned_images += CachedImage::remove_uncached_files(dir)?;
+        }
+        report.integrity = {
+            conn_db!(db);
+            let mut stmt = db.prepare("PRAGMA integrity_check")?;
+            let iter = stmt.query_map([], |row| row.get(0))?;
+            iter.collect::<rusqlite::Result<_>>()?
+        };
+        {
+            conn_db!(db);
+            db.execute_batch("PRAGMA optimize")?;
+        }
+        report.freed_pages = Self::vacuum(self.vacuum_pages)?;
+        conn_db!(db);
+        report.page_count = db.query_row("PRAGMA page_count", [], |row| row.get(0))?;
+        report.elapsed_ms = t.elapsed().as_millis() as i64;
+        db.execute(
+            "REPLACE INTO maintenancelog VALUES (?1, ?2)",
+            params![report.started, serde_json::to_string(&report)?],
+        )?;
+        Ok(report)
+    }
+
+    fn expired_unfollowed(&self, now: i64) -> Result<Vec<i64>> {
+        conn_db!(db);
+        let mut stmt = db.prepare_cached(
+            "SELECT id FROM usersync WHERE enable=0 AND unfollow_ts>0 AND unfollow_ts<?1",
+        )?;
+        let iter = stmt.query_map(params![now - self.unfollowed_secs], |row| row.get(0))?;
+        Ok(iter.filter_map(|o| o.ok()).collect())
+    }
+
+    fn freelist_count(db: DbType) -> rusqlite::Result<i64> {
+        db.query_row("PRAGMA freelist_count", [], |row| row.get(0))
+    }
+
+    /// Free up to _pages_ pages in steps of [`VACUUM_STEP_PAGES`] . A database created without
+    /// incremental auto vacuum is converted by a full vacuum first, which holds the connection
+    /// throughout.
+    fn vacuum(pages: i64) -> Result<i64> {
+        if pages <= 0 {
+            return Ok(0);
+        }
+        let (before, mode) = {
+            conn_db!(db);
+            let mode: i64 = db.query_row("PRAGMA auto_vacuum", [], |row| row.get(0))?;
+            (Self::freelist_count(db)?, mode)
+        };
+        if mode != 2 {
+            log::info!("Convert database to incremental auto vacuum");
+            conn_db!(db);
+            db.execute_batch("PRAGMA auto_vacuum = INCREMENTAL; VACUUM;")?;
+            return Ok(before - Self::freelist_count(db)?);
+        }
+        let mut left = pages.min(before);
+        while left > 0 {
+            let step = left.min(VACUUM_STEP_PAGES);
+            conn_db!(db);
+            db.execute_batch(&format!("PRAGMA incremental_vacuum({})", step))?;
+            left -= step;
+        }
+        conn_db!(db);
+        Ok(before - Self::freelist_count(db)?)
+    }
+
+    /// Report of the latest run.
+    pub fn last() -> Result<MaintenanceReport> {
+        conn_db!(db);
+        let text: String = db.query_row(
+            "SELECT report FROM maintenancelog ORDER BY started DESC LIMIT 1",
+            [],
+            |row| row.get(0),
+        )?;
+        Ok(serde_json::from_str(&text)?)
+    }
+}
+
+impl CachedImage {
+    /// Remember _url_ as _hash_ , it is fetched on the first request.
+    /// Register _url_ as _hash_ , keeping it from being pruned while not fetched yet.
+    pub fn register(hash: &str, url: &str) -> Result<()> {
+        conn_db!(db);
+        let now = Utc::now().timestamp();
+        db.execute(
+            "INSERT OR IGNORE INTO imagecache (hash, url, atime) VALUES (?1, ?2, ?3)",
+            params![hash, url, now],
+        )?;
+        db.execute(
+            "UPDATE imagecache SET atime=?2 WHERE hash=?1 AND bytes=0",
+            params![hash, now],
+        )?;
+        Ok(())
+    }
+
+    /// Remove image files in _dir_ not stored in the cache, as left by eviction races or
+    /// purged users. Only file names of image hashes are considered.
+    fn remove_uncached_files(dir: &Path) -> Result<usize> {
+        let stored: std::collections::HashSet<String> = {
+            conn_db!(db);
+            let mut stmt = db.prepare_cached("SELECT hash FROM imagecache WHERE bytes>0")?;
+            let iter = stmt.query_map([], |row| row.get(0))?;
+            iter.filter_map(|o| o.ok()).collect()
+        };
+        let entries = match std::fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(0),
+            Err(e) => return Err(e.into()),
+        };
+        let mut n = 0;
+        for entry in entries.filter_map(|e| e.ok()) {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let is_hash = name.len() == 16 && name.chars().all(|c| c.is_ascii_hexdigit());
+            if !is_hash || stored.contains(&name) {
+                continue;
             }
-        })?;
-        let iter = stmt.query_map(params![fid, start, len], |row| row.get(0))?;
-        Ok(iter.filter_map(|id| id.ok()).collect())
+            match std::fs::remove_file(entry.path()) {
+                Ok(_) => n += 1,
+                Err(e) => log::warn!("Remove image file {} error(s): {}", name, e),
+            }
+        }
+        Ok(n)
+    }
+
+    pub fn get(hash: &str) -> Result<Self> {
+        conn_db!(db);
+        Ok(db.query_row(
+            "SELECT * FROM imagecache WHERE hash=?1",
+            params![hash],
+            Self::from_row,
+        )?)
+    }
+
+    pub fn touch(hash: &str) -> Result<()> {
+        conn_db!(db);
+        db.execute(
+            "UPDATE imagecache SET atime=?2 WHERE hash=?1",
+            params![hash, Utc::now().timestamp()],
+        )?;
+        Ok(())
+    }
+
+    /// Mark _hash_ stored, then evict the least recently used images until at most _max_bytes_
+    /// are stored. Returns hashes of the evicted ones, whose files shall be removed.
+    pub fn stored(hash: &str, mime: &str, bytes: i64, max_bytes: i64) -> Result<Vec<String>> {
+        conn_db!(db);
+        db.execute(
+            "UPDATE imagecache SET mime=?2, bytes=?3, atime=?4 WHERE hash=?1",
+            params![hash, mime, bytes, Utc::now().timestamp()],
+        )?;
+        let total: i64 =
+            db.query_row("SELECT IFNULL(SUM(bytes), 0) FROM imagecache", [], |row| {
+                row.get(0)
+            })?;
+        let mut over = total - max_bytes;
+        let mut evicted = vec![];
+        if over <= 0 {
+            return Ok(evicted);
+        }
+        let mut stmt =
+            db.prepare_cached("SELECT hash, bytes FROM imagecache WHERE bytes>0 ORDER BY atime")?;
+        let iter = stmt.query_map([], |row| Ok((row.get::<_, String>(0)?, row.get(1)?)))?;
+        for (h, n) in iter.filter_map(|o| o.ok()) {
+            if over <= 0 {
+                break;
+            }
+            over -= n;
+            evicted.push(h);
+        }
+        for h in evicted.iter() {
+            db.execute(
+                "UPDATE imagecache SET mime='', bytes=0 WHERE hash=?1",
+                params![h],
+            )?;
+        }
+        Ok(evicted)
+    }
+}
+
+impl BackupFile {
+    fn of(path: &Path) -> Result<Self> {
+        let meta = std::fs::metadata(path)?;
+        let mtime = meta
+            .modified()?
+            .duration_since(std::time::UNIX_EPOCH)?
+            .as_secs() as i64;
+        Ok(Self {
+            name: path
+                .file_name()
+                .map(|s| s.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            bytes: meta.len(),
+            mtime,
+        })
+    }
+}
+
+impl Backup {
+    /// Copy the database into a new file by the SQLite backup API, then drop the oldest
+    /// backups beyond _keep_ . The copy is read through a connection of its own, leaving the
+    /// shared one free, and restarts by itself if the database is written meanwhile.
+    pub fn run(&self) -> Result<BackupFile> {
+        std::fs::create_dir_all(&self.dir)?;
+        let name = format!("cache-{}.db3", Utc::now().format("%Y%m%d-%H%M%S"));
+        let path = self.dir.join(&name);
+        {
+            let src = Connection::open_with_flags(DB_PATH, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+            let mut dst = Connection::open(&path)?;
+            rusqlite::backup::Backup::new(&src, &mut dst)?.run_to_completion(
+                BACKUP_STEP_PAGES,
+                std::time::Duration::from_millis(10),
+                None,
+            )?;
+        }
+        log::info!("Database backed up to {}", path.display());
+        for old in self.list()?.iter().skip(self.keep.max(1)) {
+            let p = self.dir.join(&old.name);
+            match std::fs::remove_file(&p) {
+                Ok(_) => log::info!("Rotated out backup {}", p.display()),
+                Err(e) => log::warn!("Remove backup {} error(s): {}", p.display(), e),
+            }
+        }
+        BackupFile::of(&path)
+    }
+
+    /// Backups in _dir_ , latest first.
+    pub fn list(&self) -> Result<Vec<BackupFile>> {
+        if !self.dir.exists() {
+            return Ok(vec![]);
+        }
+        let mut r: Vec<BackupFile> = std::fs::read_dir(&self.dir)?
+            .filter_map(|e| e.ok())
+            .map(|e| e.path())
+            .filter(|p| {
+                p.file_name()
+                    .and_then(|s| s.to_str())
+                    .map(|s| s.starts_with("cache-") && s.ends_with(".db3"))
+                    .unwrap_or(false)
+            })
+            .filter_map(|p| BackupFile::of(&p).ok())
+            .collect();
+        r.sort_by(|a, b| b.name.cmp(&a.name));
+        Ok(r)
+    }
+
+    /// Replace the database at _to_ with backup _from_ , the replaced one is kept beside as
+    /// `*.before-restore` . The backup must pass the integrity check and be of a schema version
+    /// not newer than this build. Fails if any process has the database opened.
+    pub fn restore(from: &Path, to: &Path) -> Result<()> {
+        let _lock = lock_db(to, true)?;
+        {
+            let db = Connection::open_with_flags(from, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+            let check: String = db.query_row("PRAGMA integrity_check", [], |row| row.get(0))?;
+            if check != "ok" {
+                return Err(anyhow!("backup {} is corrupted: {}", from.display(), check));
+            }
+            let version: i64 = db.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+            if !(0..=SCHEMA_VERSION).contains(&version) {
+                return Err(anyhow!(
+                    "backup {} has schema version {}, expect at most {}",
+                    from.display(),
+                    version,
+                    SCHEMA_VERSION
+                ));
+            }
+            db.query_row("SELECT COUNT(*) FROM usersync", [], |row| {
+                row.get::<_, i64>(0)
+            })
+            .map_err(|e| anyhow!("backup {} is no cache database: {}", from.display(), e))?;
+        }
+        let staged = to.with_extension("db3.restore");
+        std::fs::copy(from, &staged)?;
+        if to.exists() {
+            std::fs::rename(to, to.with_extension("db3.before-restore"))?;
+        }
+        std::fs::rename(&staged, to)?;
+        log::info!("Database restored from {}", from.display());
+        Ok(())
+    }
+}
+
+impl LiveAreaHit {
+    /// Count followed user _uid_ seen living in the area, once every
+    /// [`AREA_HIT_PERIOD_SECS`] .
+    pub fn hit<T: ToString>(uid: i64, parent_id: i64, area_id: i64, name: T) -> Result<()> {
+        conn_db!(db);
+        Self::db_hit(db, uid, parent_id, area_id, name)
+    }
+
+    fn db_hit<T: ToString>(
+        db: DbType,
+        uid: i64,
+        parent_id: i64,
+        area_id: i64,
+        name: T,
+    ) -> Result<()> {
+        let now = Utc::now().timestamp();
+        let period = now / AREA_HIT_PERIOD_SECS;
+        let last: Option<i64> = db
+            .query_row(
+                "SELECT period FROM liveareauser WHERE parent_id=?1 AND area_id=?2 AND uid=?3",
+                params![parent_id, area_id, uid],
+                |row| row.get(0),
+            )
+            .optional()?;
+        if last == Some(period) {
+            return Ok(());
+        }
+        db.execute(
+            "REPLACE INTO liveareauser VALUES (?1, ?2, ?3, ?4)",
+            params![parent_id, area_id, uid, period],
+        )?;
+        db.execute(
+            "INSERT OR IGNORE INTO liveareahit (parent_id, area_id, mtime) VALUES (?1, ?2, ?3)",
+            params![parent_id, area_id, now],
+        )?;
+        db.execute(
+            "UPDATE liveareahit SET hits=hits+1, name=?3, mtime=?4 \
+            WHERE parent_id=?1 AND area_id=?2",
+            params![parent_id, area_id, name.to_string(), now],
+        )?;
+        Ok(())
+    }
+
+    /// All areas, most hit first.
+    pub fn all() -> Result<Vec<Self>> {
+        conn_db!(db);
+        Self::db_all(db)
+    }
+
+    fn db_all(db: DbType) -> Result<Vec<Self>> {
+        let mut stmt = db.prepare_cached("SELECT * FROM liveareahit ORDER BY hits DESC")?;
+        let iter = stmt.query_map([], Self::from_row)?;
+        Ok(iter.filter_map(|o| o.ok()).collect())
     }
 }
 
 impl FilterMeta {
-    pub fn new<T: ToString>(name: T) -> Result<Self> {
+    /// New filter, a smart one if _query_ is given.
+    pub fn new<T: ToString>(name: T, query: Option<UserQuerySpec>) -> Result<Self> {
+        let saved = match &query {
+            Some(spec) => {
+                UserQuery::try_from(spec)?;
+                Some(serde_json::to_string(spec)?)
+            }
+            None => None,
+        };
         conn_db!(db);
+        let now = Utc::now().timestamp();
         db.execute(
-            "INSERT INTO filtermeta (name) VALUES (?1)",
-            params![name.to_string()],
+            "INSERT INTO filtermeta (name, visit_ts, query) VALUES (?1, ?2, ?3)",
+            params![name.to_string(), now, saved],
         )?;
         Ok(Self {
             fid: db.query_row(
@@ -565,14 +2508,82 @@ impl FilterMeta {
                 |row| row.get(0),
             )?,
             name: name.to_string(),
+            visit_ts: now,
+            counts: Default::default(),
+            query,
         })
     }
 
+    /// All filters with their counts.
     pub fn all() -> Result<Vec<Self>> {
         conn_db!(db);
         let mut stmt = db.prepare_cached("SELECT * FROM filtermeta ORDER BY fid ASC")?;
         let iter = stmt.query_map([], FilterMeta::from_row)?;
-        Ok(iter.filter_map(|o| o.ok()).collect())
+        Ok(iter
+            .filter_map(|o| o.ok())
+            .map(|mut f| {
+                f.counts = Self::db_counts(db, &f)
+                    .map_err(|e| log::warn!("Count filter {} error(s): {}", f.fid, e))
+                    .unwrap_or_default();
+                f
+            })
+            .collect())
+    }
+
+    fn db_counts(db: DbType, f: &FilterMeta) -> Result<FilterCounts> {
+        let query = match &f.query {
+            Some(spec) => UserQuery::try_from(spec)?,
+            None => UserQuery::filter(f.fid),
+        };
+        let mut clause = Clause::after(1);
+        let filter = query.where_sql(db, &mut clause, 0)?;
+        let values = std::iter::once(Value::from(f.visit_ts)).chain(clause.values);
+        Ok(db.query_row(
+            &format!(
+                "SELECT COUNT(*), \
+                    IFNULL(SUM(IFNULL(live_open, 0)), 0), \
+                    IFNULL(SUM((SELECT COUNT(*) FROM videoowner \
+                        WHERE videoowner.uid=usersync.id AND timestamp>?1)), 0) \
+                FROM usersync \
+                LEFT JOIN userinfo ON userinfo.id=usersync.id \
+                WHERE {}",
+                filter
+            ),
+            params_from_iter(values),
+            |row| {
+                Ok(FilterCounts {
+                    members: row.get(0)?,
+                    live: row.get(1)?,
+                    new_videos: row.get(2)?,
+                })
+            },
+        )?)
+    }
+
+    /// Replace the saved query of smart filter _fid_ .
+    pub fn set_query(fid: i64, spec: &UserQuerySpec) -> Result<()> {
+        UserQuery::try_from(spec)?;
+        let saved = serde_json::to_string(spec)?;
+        conn_db!(db);
+        let n = db.execute(
+            "UPDATE filtermeta SET query=?2 WHERE fid=?1 AND query IS NOT NULL",
+            params![fid, saved],
+        )?;
+        if n == 0 {
+            return Err(anyhow!("filter {} is not a smart filter", fid));
+        }
+        Ok(())
+    }
+
+    /// Mark filter _fid_ visited now, which resets its new videos count.
+    pub fn visit(fid: i64) {
+        conn_db!(db);
+        db.execute(
+            "UPDATE filtermeta SET visit_ts=?2 WHERE fid=?1",
+            params![fid.max(0), Utc::now().timestamp()],
+        )
+        .map_err(|e| log::warn!("Update filtermeta visit_ts error(s): {}", e))
+        .ok();
     }
 }
 
@@ -581,14 +2592,13 @@ impl TryFrom<i64> for FilterMeta {
 
     fn try_from(fid: i64) -> Result<Self> {
         conn_db!(db);
-        Ok(Self {
-            fid,
-            name: db.query_row(
-                "SELECT name FROM filtermeta WHERE fid=?1",
-                params![fid],
-                |row| row.get(0),
-            )?,
-        })
+        let mut f = db.query_row(
+            "SELECT * FROM filtermeta WHERE fid=?1",
+            params![fid],
+            FilterMeta::from_row,
+        )?;
+        f.counts = Self::db_counts(db, &f)?;
+        Ok(f)
     }
 }
 
@@ -597,3 +2607,251 @@ impl std::fmt::Display for User {
         self.uid.fmt(f)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn name_cmp_orders_scripts() {
+        let mut names = vec!["한국", "中文", "かな", "abc", "123", "Zed"];
+        names.sort_by(|a, b| name_cmp(a, b));
+        assert_eq!(names, vec!["123", "abc", "Zed", "かな", "한국", "中文"]);
+    }
+
+    #[test]
+    fn name_cmp_ignores_case_and_width() {
+        assert_eq!(name_cmp("ABC", "abc"), Ordering::Equal);
+        assert_eq!(name_cmp("ＡＢＣ", "abc"), Ordering::Equal);
+        assert_eq!(name_cmp("１２", "12"), Ordering::Equal);
+        assert_eq!(name_cmp("ab", "abc"), Ordering::Less);
+        assert_eq!(name_cmp("一", "丁"), Ordering::Less);
+    }
+
+    #[test]
+    fn order_parses_keys_and_directions() {
+        let order: Order = "priority, name:asc ,video:asc".parse().unwrap();
+        assert_eq!(
+            order.keys,
+            vec![
+                (OrderKey::Priority, true),
+                (OrderKey::Name, false),
+                (OrderKey::LatestVideo, false),
+            ]
+        );
+        assert_eq!("name".parse::<Order>().unwrap(), Order::by(OrderKey::Name));
+        assert_eq!("default".parse::<OrderKey>().unwrap(), OrderKey::Rowid);
+    }
+
+    #[test]
+    fn order_rejects_unknown() {
+        assert!("views".parse::<OrderKey>().is_err());
+        assert!("Name".parse::<OrderKey>().is_err());
+        assert!("".parse::<Order>().is_err());
+        assert!("name,".parse::<Order>().is_err());
+        assert!("name:up".parse::<Order>().is_err());
+        assert!("name:asc:desc".parse::<Order>().is_err());
+    }
+
+    /// Fully migrated database of users 1 to 4: Alice and bob in filter 2, bob living with 500
+    /// viewers, Carol tagged `vtuber` , dave unfollowed.
+    fn query_db() -> Connection {
+        let db = Connection::open_in_memory().unwrap();
+        db.execute_batch(include_str!("../assets/db_init.sql"))
+            .unwrap();
+        db.create_collation("NAME", name_cmp).unwrap();
+        migrate(&db).unwrap();
+        db.execute_batch(
+            "INSERT INTO usersync (id, ctime, new_video_title) VALUES
+                (1, '', ''), (2, '', ''), (3, '', '');
+            INSERT INTO usersync (id, enable, ctime, new_video_title) VALUES (4, 0, '', '');
+            INSERT INTO userinfo VALUES
+                (1, 'Alice', '', NULL, NULL, 0, 0),
+                (2, 'bob', '', NULL, NULL, 1, 500),
+                (3, 'Carol', '', NULL, NULL, 0, 0),
+                (4, 'dave', '', NULL, NULL, 0, 0);
+            INSERT INTO filtermeta (fid, name) VALUES (2, 'two');
+            INSERT INTO userfilters VALUES (1, 2, 10), (2, 2, 20);
+            INSERT INTO usertag VALUES (3, 'vtuber');",
+        )
+        .unwrap();
+        db
+    }
+
+    fn query_ids(db: &Connection, spec: UserQuerySpec) -> Result<Vec<i64>> {
+        let mut ids = UserQuery::try_from(&spec)?.db_list(db, 0, 100)?;
+        ids.sort_unstable();
+        Ok(ids)
+    }
+
+    fn is(predicates: &str) -> UserQuerySpec {
+        UserQuerySpec {
+            is: Some(predicates.to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn query_filters_and_predicates() {
+        let db = query_db();
+        let any = |fids: &str| UserQuerySpec {
+            any: Some(fids.to_string()),
+            ..Default::default()
+        };
+        assert_eq!(query_ids(&db, Default::default()).unwrap(), vec![1, 2, 3]);
+        assert_eq!(query_ids(&db, any("2")).unwrap(), vec![1, 2]);
+        let not = UserQuerySpec {
+            not: Some("2".into()),
+            ..Default::default()
+        };
+        assert_eq!(query_ids(&db, not).unwrap(), vec![3]);
+        assert_eq!(query_ids(&db, is("live")).unwrap(), vec![2]);
+        assert_eq!(query_ids(&db, is("viewers:500")).unwrap(), vec![2]);
+        assert!(query_ids(&db, is("viewers:501")).unwrap().is_empty());
+        assert_eq!(query_ids(&db, is("disabled")).unwrap(), vec![4]);
+        assert_eq!(query_ids(&db, is("name:ALI")).unwrap(), vec![1]);
+        assert_eq!(query_ids(&db, is("tag:vtuber")).unwrap(), vec![3]);
+        assert_eq!(query_ids(&db, is("text:vtu, text:car")).unwrap(), vec![3]);
+        assert_eq!(query_ids(&db, is("live,name:bob")).unwrap(), vec![2]);
+    }
+
+    #[test]
+    fn query_binds_texts() {
+        let db = query_db();
+        let q = UserQuery::try_from(&is("name:') OR 1=1 --")).unwrap();
+        let mut clause = Clause::after(2);
+        let sql = q.where_sql(&db, &mut clause, 0).unwrap();
+        assert!(!sql.contains("1=1"), "{}", sql);
+        assert!(sql.contains("?3"), "{}", sql);
+        assert_eq!(clause.values, vec![Value::from("') OR 1=1 --".to_string())]);
+        for spec in &["name:') OR 1=1 --", "tag:' OR '1'='1", "text:%"] {
+            assert!(query_ids(&db, is(spec)).unwrap().is_empty(), "{}", spec);
+        }
+    }
+
+    #[test]
+    fn query_smart_filters() {
+        let db = query_db();
+        db.execute_batch(
+            r#"INSERT INTO filtermeta (fid, name, query) VALUES
+                (5, 'living', '{"is":"live"}'),
+                (6, 'loop', '{"any":"6"}'),
+                (7, 'not living', '{"any":"2","not":"5"}');"#,
+        )
+        .unwrap();
+        let all = |fids: &str| UserQuerySpec {
+            all: Some(fids.to_string()),
+            ..Default::default()
+        };
+        assert_eq!(query_ids(&db, all("5")).unwrap(), vec![2]);
+        assert_eq!(query_ids(&db, all("7")).unwrap(), vec![1]);
+        let e = query_ids(&db, all("6")).unwrap_err();
+        assert!(e.to_string().contains("too deep"), "{}", e);
+    }
+
+    #[test]
+    fn query_orders() {
+        let db = query_db();
+        let by = |order: &str| {
+            UserQuery::try_from(&UserQuerySpec {
+                order: Some(order.to_string()),
+                ..Default::default()
+            })
+            .unwrap()
+            .db_list(&db, 0, 100)
+            .unwrap()
+        };
+        assert_eq!(by("name"), vec![1, 2, 3]);
+        assert_eq!(by("name:desc"), vec![3, 2, 1]);
+        assert_eq!(by("live"), vec![2]);
+        assert!(UserQuery::try_from(&UserQuerySpec {
+            order: Some("views".into()),
+            ..Default::default()
+        })
+        .is_err());
+    }
+
+    #[test]
+    fn predicate_parses() {
+        let cases = [
+            ("live", Predicate::Live),
+            ("viewers:100", Predicate::Viewers(100)),
+            ("uploaded:7", Predicate::UploadedWithin(7)),
+            ("name:a:b", Predicate::NameContains("a:b".into())),
+            ("tag:vtuber", Predicate::Tag("vtuber".into())),
+            ("text:歌", Predicate::Text("歌".into())),
+            ("never_refreshed", Predicate::NeverRefreshed),
+            ("disabled", Predicate::Disabled),
+            ("muted", Predicate::Muted),
+        ];
+        for (s, p) in cases.iter() {
+            assert_eq!(&s.parse::<Predicate>().unwrap(), p, "{}", s);
+        }
+    }
+
+    #[test]
+    fn predicate_rejects_invalid() {
+        for s in &[
+            "",
+            "living",
+            "Live",
+            "live:1",
+            "viewers",
+            "viewers:",
+            "viewers:many",
+            "uploaded:1.5",
+            "name:",
+            "tag:",
+            "text:",
+            "color:red",
+        ] {
+            assert!(s.parse::<Predicate>().is_err(), "{}", s);
+        }
+    }
+
+    #[test]
+    fn set_live_keeps_ctime() {
+        let db = query_db();
+        let ctime = |db: &Connection| -> i64 {
+            db.query_row("SELECT ctimestamp FROM usersync WHERE id=1", [], |r| {
+                r.get(0)
+            })
+            .unwrap()
+        };
+        let before = ctime(&db);
+        let u = User::new(1);
+        let mut info = u.db_info(&db).unwrap();
+        info.live_open = Some(true);
+        info.live_room_title = Some("title".to_string());
+        info.live_entropy = Some(42);
+        u.db_set_live(&db, &info);
+        assert_eq!(ctime(&db), before);
+        let info = u.db_info(&db).unwrap();
+        assert_eq!(info.live_open, Some(true));
+        assert_eq!(info.live_room_title.as_deref(), Some("title"));
+        assert_eq!(info.live_entropy, Some(42));
+        let live_start: i64 = db
+            .query_row("SELECT live_start_ts FROM usersync WHERE id=1", [], |r| {
+                r.get(0)
+            })
+            .unwrap();
+        assert!(live_start > 0);
+        u.db_set_info(&db, &info);
+        assert!(ctime(&db) > before);
+    }
+
+    #[test]
+    fn live_area_hits_count_users_once() {
+        let db = query_db();
+        for uid in [1, 1, 2] {
+            LiveAreaHit::db_hit(&db, uid, 9, 371, "vup").unwrap();
+        }
+        LiveAreaHit::db_hit(&db, 1, 9, 372, "other").unwrap();
+        let hits: Vec<(i64, i64)> = LiveAreaHit::db_all(&db)
+            .unwrap()
+            .iter()
+            .map(|h| (h.area_id, h.hits))
+            .collect();
+        assert_eq!(hits, vec![(371, 2), (372, 1)]);
+    }
+}
@@ -1,13 +1,24 @@
-use crate::{db, Result};
+use crate::{
+    config, db,
+    i18n::{self, Locale},
+    Result,
+};
 use chrono::{DateTime, Local};
-use rand::{seq::SliceRandom, Rng};
+use rand::Rng;
 use serde_derive::{Deserialize, Serialize};
 use std::convert::TryInto;
 use std::fmt;
 use std::io::{self, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Once, RwLock};
 use std::time::{Duration, Instant};
-use tokio::sync::{mpsc, watch};
+use tokio::sync::{mpsc, oneshot, watch};
+use utoipa::ToSchema;
+
+pub mod live;
+pub mod policy;
+pub mod remote;
+pub mod sweep;
 
 lazy_static::lazy_static! {
     static ref SENDER: RwLock<Option<mpsc::Sender<Command>>> = RwLock::new(None);
@@ -70,6 +81,83 @@ pub async fn done_shutdown() {
 
 pub const CHANNEL_CAP: usize = 128;
 pub const SILENCE_HIP_TH: u64 = 5;
+pub const STATS_COMPACT_INTERVAL: Duration = Duration::from_secs(3600);
+pub const MAINTENANCE_CHECK_INTERVAL: Duration = Duration::from_secs(600);
+pub const BACKUP_CHECK_INTERVAL: Duration = Duration::from_secs(600);
+
+/// Backups at the configured place.
+pub fn backups() -> db::Backup {
+    let c = &config::get().backup;
+    db::Backup {
+        dir: c.dir.clone().into(),
+        keep: c.keep,
+    }
+}
+
+static MAINTAINING: AtomicBool = AtomicBool::new(false);
+
+/// Run long blocking database work _f_ off the async workers, refused while another run
+/// flagged by _busy_ is still going.
+async fn run_blocking<T, F>(busy: &'static AtomicBool, what: &str, f: F) -> Result<T>
+where
+    T: Send + 'static,
+    F: FnOnce() -> Result<T> + Send + 'static,
+{
+    if busy.swap(true, Ordering::AcqRel) {
+        return Err(anyhow::anyhow!("{} is already running", what));
+    }
+    // cleared by the task itself, which goes on even if the caller is dropped
+    tokio::task::spawn_blocking(move || {
+        let r = f();
+        busy.store(false, Ordering::Release);
+        r
+    })
+    .await?
+}
+
+static BACKING_UP: AtomicBool = AtomicBool::new(false);
+
+/// Take a backup at the configured place.
+pub async fn backup() -> Result<db::BackupFile> {
+    run_blocking(&BACKING_UP, "Database backup", || backups().run()).await
+}
+
+/// Replace the database with backup _from_ , refused while any process has it opened.
+pub async fn restore(from: std::path::PathBuf) -> Result<()> {
+    tokio::task::spawn_blocking(move || db::Backup::restore(&from, db::DB_PATH.as_ref())).await?
+}
+
+/// Run database maintenance with the configured retention.
+pub async fn maintain() -> Result<db::MaintenanceReport> {
+    let c = &config::get().maintenance;
+    let maintenance = db::Maintenance {
+        keep_videos: c.keep_videos,
+        unfollowed_secs: c.unfollowed_days * 86400,
+        vacuum_pages: c.vacuum_pages,
+        image_dir: Some(config::get().image_cache.dir.clone().into()),
+    };
+    let report = run_blocking(&MAINTAINING, "Database maintenance", move || {
+        maintenance.run()
+    })
+    .await?;
+    log::info!("Database maintenance done: {:?}", report);
+    Ok(report)
+}
+
+fn is_backup_due() -> bool {
+    let interval = config::get().backup.interval_hours * 3600;
+    if interval <= 0 {
+        return false;
+    }
+    let last = match backups().list() {
+        Ok(files) => files.first().map(|f| f.mtime).unwrap_or(0),
+        Err(e) => {
+            log::error!("List database backups error(s): {}", e);
+            return false;
+        }
+    };
+    chrono::Utc::now().timestamp() - last >= interval
+}
 
 struct Engine {
     cmd: CommandRunner,
@@ -83,6 +171,79 @@ pub enum Command {
     Activate,
     ForceSilence(bool),
     Shutdown,
+    /// Run _cmd_ and reply its outcome through _ack_. A _queued_ refresh which finds no token is
+    /// saved as a refresh job instead of being dropped, and is replied as `Queued`.
+    Acked {
+        cmd: Box<Command>,
+        queued: bool,
+        ack: oneshot::Sender<Outcome>,
+    },
+}
+
+type Ack = (bool, oneshot::Sender<Outcome>);
+
+impl Command {
+    /// The refresh job to save if this command can not be run now.
+    fn job(&self) -> Option<(i64, db::JobKind)> {
+        match self {
+            Self::Refresh(uid) => Some((*uid, db::JobKind::Manual)),
+            Self::Follow(true, uid) => Some((*uid, db::JobKind::Follow)),
+            _ => None,
+        }
+    }
+
+    /// _self_ to be saved as a refresh job if it finds no token, its outcome is not waited.
+    pub fn queued(self) -> Self {
+        let (ack, _) = oneshot::channel();
+        Self::Acked {
+            cmd: Box::new(self),
+            queued: true,
+            ack,
+        }
+    }
+
+    fn split_ack(self) -> (Self, Option<Ack>) {
+        match self {
+            Self::Acked { cmd, queued, ack } => (*cmd, Some((queued, ack))),
+            cmd => (cmd, None),
+        }
+    }
+}
+
+/// Outcome of an acknowledged command.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Outcome {
+    Done,
+    NoToken,
+    Queued,
+    Failed(String),
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Done => write!(f, "done"),
+            Self::NoToken => write!(f, "rejected for no token"),
+            Self::Queued => write!(f, "queued"),
+            Self::Failed(why) => write!(f, "failed: {}", why),
+        }
+    }
+}
+
+/// Send _cmd_ to the engine and wait for its outcome.
+pub async fn ask(cmd: Command, queued: bool) -> Outcome {
+    let (ack, rx) = oneshot::channel();
+    let cmd = Command::Acked {
+        cmd: Box::new(cmd),
+        queued,
+        ack,
+    };
+    if let Err(e) = handle().send(cmd).await {
+        return Outcome::Failed(format!("engine unavailable: {}", e));
+    }
+    rx.await
+        .unwrap_or_else(|e| Outcome::Failed(format!("engine dropped command: {}", e)))
 }
 
 #[derive(Clone, Serialize, Deserialize)]
@@ -98,38 +259,58 @@ impl Default for RefreshStatus {
     }
 }
 
+/// Refresh status and the active time-of-day policy.
 #[derive(Default, Clone, Serialize, Deserialize)]
-pub struct Status(pub RefreshStatus);
+pub struct Status(pub RefreshStatus, pub Option<policy::Policy>);
 
-impl fmt::Display for Status {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self.0 {
-            RefreshStatus::Fast => write!(f, "激活自动刷新"),
-            RefreshStatus::Slow => write!(f, "低速自动刷新"),
+impl Status {
+    /// Description in _locale_ .
+    pub fn describe(&self, locale: Locale) -> String {
+        let base = match self.0 {
+            RefreshStatus::Fast => locale.text("status_fast").to_string(),
+            RefreshStatus::Slow => locale.text("status_slow").to_string(),
             RefreshStatus::Silence(i, _) => {
                 let d = i - Local::now();
                 let day = chrono::Duration::days(1);
-                write!(
-                    f,
-                    "停止自动更新至{}",
-                    i.format(if d > day {
-                        "%Y-%m-%d %H:%M:%S"
-                    } else {
-                        "%H:%M:%S"
-                    })
-                )
+                let until = i.format(if d > day {
+                    "%Y-%m-%d %H:%M:%S"
+                } else {
+                    "%H:%M:%S"
+                });
+                i18n::fmt(locale.text("status_silence"), &[&until])
             }
+        };
+        match &self.1 {
+            Some(policy) => format!("{} [{}]", base, policy.describe(locale)),
+            None => base,
         }
     }
 }
 
-#[derive(Clone, Default, Serialize, Deserialize)]
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.describe(Locale::current()))
+    }
+}
+
+#[derive(Clone, Default, Serialize, Deserialize, ToSchema)]
 pub struct Event {
     pub done_refresh: Option<i64>,
+    #[schema(value_type = Object)]
     pub status: Status,
     pub status_desc: String,
     pub new_live_msgid: i64,
     pub new_live_name: String,
+    pub rename_msgid: i64,
+    pub rename_uid: i64,
+    pub rename_from: String,
+    pub rename_to: String,
+    pub new_dynamic_msgid: i64,
+    pub new_dynamic_uid: i64,
+    pub new_dynamic_name: String,
+    pub new_dynamic_text: String,
+    pub filters: Vec<db::FilterMeta>,
+    pub live_latency: live::LiveLatency,
 }
 
 fn enforce_init() {
@@ -184,13 +365,18 @@ impl CommandRunner {
         let _running = will_shutdown();
         while let Some(cmd) = self.receiver.recv().await {
             let mut d = self.dispatcher.clone();
-            let quit = matches!(cmd, Command::Shutdown);
-            tokio::spawn(async move {
+            if matches!(cmd, Command::Shutdown) {
+                // Save the commands left behind instead of running them while shutting down.
+                self.receiver.close();
+                while let Ok(left) = self.receiver.try_recv() {
+                    save_left_command(left);
+                }
                 d.dispatch(cmd).await;
-            });
-            if quit {
                 break;
             }
+            tokio::spawn(async move {
+                d.dispatch(cmd).await;
+            });
         }
         log::info!("CommandRunner stopped");
     }
@@ -199,17 +385,23 @@ impl CommandRunner {
 impl CommandDispatcher {
     pub async fn dispatch(&mut self, cmd: Command) {
         log::trace!("Command refresh type");
-        self.refresh_sender.send(cmd).await.ok();
+        // the refresh runner is gone after shutdown
+        if let Err(mpsc::error::SendError(cmd)) = self.refresh_sender.send(cmd).await {
+            save_left_command(cmd);
+        }
     }
 }
 
 struct RefreshRunner {
     receiver: mpsc::Receiver<Command>,
     token: RefreshBucket,
-    api: bilibili_api_rs::Client,
+    remote: remote::Remote,
     evtx: watch::Sender<Event>,
     silence_cnt: u64,
     silence_reason: String,
+    schedule: policy::Schedule,
+    sweep: sweep::LiveSweep,
+    live: live::LivePoll,
 }
 
 impl RefreshRunner {
@@ -218,9 +410,12 @@ impl RefreshRunner {
             receiver,
             evtx,
             token: Default::default(),
-            api: bilibili_api_rs::Client::new(),
+            remote: remote::Remote::new(),
             silence_cnt: 0,
             silence_reason: Default::default(),
+            schedule: policy::Schedule::new(&config::get().schedule),
+            sweep: sweep::LiveSweep::new(&config::get().live_areas),
+            live: live::LivePoll::new(config::get().live_poll),
         }
     }
 
@@ -230,21 +425,27 @@ impl RefreshRunner {
         let slowdown_duration = 32 * REFRESH_BUCKET_TIK_INTERVAL;
         let auto_refresh = tokio::time::sleep(REFRESH_BUCKET_TIK_INTERVAL);
         let auto_slowdown = tokio::time::sleep(slowdown_duration);
+        let live_interval = Duration::from_secs(self.live.config.interval_secs.max(10));
+        let live_poll = tokio::time::sleep(live_interval);
+        let compact_stats = tokio::time::sleep(STATS_COMPACT_INTERVAL);
+        let maintenance = tokio::time::sleep(MAINTENANCE_CHECK_INTERVAL);
+        let backup = tokio::time::sleep(BACKUP_CHECK_INTERVAL);
         tokio::pin!(auto_refresh);
         tokio::pin!(auto_slowdown);
+        tokio::pin!(live_poll);
+        tokio::pin!(compact_stats);
+        tokio::pin!(maintenance);
+        tokio::pin!(backup);
         let factors: Vec<f32> = {
             let mut rng = rand::thread_rng();
             (0..100).map(|_| rng.gen_range(1.0..2.0)).collect()
         };
         let mut factor_i: usize = 0;
-        let live_pagens: Vec<i32> = {
+        let dices: Vec<f32> = {
             let mut rng = rand::thread_rng();
-            let mut v: Vec<i32> = (1..21).collect();
-            v.shuffle(&mut rng);
-            v.into_iter().flat_map(|x| [0, x]).collect()
+            (0..100).map(|_| rng.gen_range(0.0..1.0)).collect()
         };
-        log::info!("init pseudo random live_pagens: {:?}", live_pagens);
-        let mut live_pagens_i: usize = 0;
+        let mut dice_i: usize = 0;
 
         loop {
             tokio::select! {
@@ -257,46 +458,100 @@ impl RefreshRunner {
                     auto_slowdown.as_mut().reset(tokio::time::Instant::now() + slowdown_duration);
                     self.status_change(RefreshStatus::Fast);
                     self.token.set_interval(REFRESH_BUCKET_TIK_INTERVAL);
-                    match cmd.unwrap() {
-                        Command::Refresh(uid) => self.try_refresh(db::User::new(uid), 0).await,
-                        Command::Follow(enable, uid) => {
-                            let u = db::User::new(uid);
-                            u.enable(enable);
-                            if enable {
-                                self.try_refresh(u, 0).await;
-                            }
-                        },
-                        Command::Activate => log::info!("Command Activate force token bucket high speed"),
-                        Command::ForceSilence(flag) => {
-                            log::info!("Command ForceSilence {}", flag);
-                            if flag {
-                                self.on_remote_api_err("Forced");
-                            } else {
-                                self.on_remote_api_ok();
-                                self.token.available_now();
-                            }
-                            self.status_change(RefreshStatus::Fast);
-                        },
-                        Command::Shutdown => break,
+                    let (cmd, ack) = cmd.unwrap().split_ack();
+                    if matches!(cmd, Command::Shutdown) {
+                        self.save_left_commands();
+                        break;
+                    }
+                    let job = cmd.job();
+                    let mut outcome = self.execute(cmd).await;
+                    let queued = ack.as_ref().map(|(queued, _)| *queued).unwrap_or(false);
+                    if let (true, Outcome::NoToken, Some((uid, kind))) = (queued, &outcome, job) {
+                        outcome = save_job(uid, kind);
+                    }
+                    if let Some((_, ack)) = ack {
+                        log::debug!("Acked command outcome: {}", outcome);
+                        ack.send(outcome).ok();
                     }
                 }
                 _ = &mut auto_refresh => {
+                    let policy = self.schedule.active(Local::now().time()).clone();
+                    self.policy_change(&policy);
                     let factor: f32 = factors[factor_i];
                     factor_i += 1;
                     if factor_i >= factors.len() {
                         factor_i = 0;
                     }
-                    let pn: i32 = live_pagens[live_pagens_i];
-                    live_pagens_i += 1;
-                    if live_pagens_i >= live_pagens.len() {
-                        live_pagens_i = 0;
+                    auto_refresh.as_mut().reset(tokio::time::Instant::now() + REFRESH_BUCKET_TIK_INTERVAL.mul_f32(factor * policy.pace));
+                    match db::RefreshJob::next() {
+                        Ok(Some(job)) => {
+                            log::debug!("Run refresh job {:?}", job);
+                            self.try_refresh(db::User::new(job.uid)).await;
+                            continue;
+                        }
+                        Ok(None) => (),
+                        Err(e) => log::error!("Database query refresh job error(s): {}", e),
+                    }
+                    if policy.quiet {
+                        log::trace!("Skip auto refresh in quiet policy {}", policy.name);
+                        continue;
+                    }
+                    let dice: f32 = dices[dice_i];
+                    dice_i += 1;
+                    if dice_i >= dices.len() {
+                        dice_i = 0;
+                    }
+                    if dice < policy.live_share {
+                        // dice / live_share is again uniform in [0, 1)
+                        if let Some((area, pn)) = self.sweep.next(dice / policy.live_share) {
+                            self.try_sweep(area, pn).await;
+                        }
+                        continue;
                     }
-                    auto_refresh.as_mut().reset(tokio::time::Instant::now() + REFRESH_BUCKET_TIK_INTERVAL.mul_f32(factor));
                     match db::User::oldest_ctime_user() {
-                        Ok(user) => self.try_refresh(user, pn).await,
+                        Ok(user) => {
+                            self.try_refresh(user).await;
+                        }
                         Err(e) => log::error!("Database query oldest ctime user error(s): {}", e),
                     }
                 }
+                _ = &mut live_poll => {
+                    live_poll.as_mut().reset(tokio::time::Instant::now() + live_interval);
+                    if self.schedule.active(Local::now().time()).quiet {
+                        continue;
+                    }
+                    self.try_poll_lives(live_interval).await;
+                }
+                _ = &mut compact_stats => {
+                    compact_stats.as_mut().reset(tokio::time::Instant::now() + STATS_COMPACT_INTERVAL);
+                    let stats = &config::get().stats;
+                    match db::UserStat::compact(stats.raw_days * 86400, stats.keep_days * 86400) {
+                        Ok(n) => log::debug!("Compacted {} user stats samples", n),
+                        Err(e) => log::error!("Compact user stats error(s): {}", e),
+                    }
+                }
+                _ = &mut maintenance => {
+                    maintenance.as_mut().reset(tokio::time::Instant::now() + MAINTENANCE_CHECK_INTERVAL);
+                    if self.is_maintenance_due() {
+                        tokio::spawn(async {
+                            maintain()
+                                .await
+                                .map_err(|e| log::error!("Database maintenance error(s): {}", e))
+                                .ok();
+                        });
+                    }
+                }
+                _ = &mut backup => {
+                    backup.as_mut().reset(tokio::time::Instant::now() + BACKUP_CHECK_INTERVAL);
+                    if is_backup_due() {
+                        tokio::spawn(async {
+                            backup()
+                                .await
+                                .map_err(|e| log::error!("Database backup error(s): {}", e))
+                                .ok();
+                        });
+                    }
+                }
                 _ = &mut auto_slowdown => {
                     auto_slowdown.as_mut().reset(tokio::time::Instant::now() + REFRESH_BUCKET_TIK_INTERVAL * 3600);
                     log::warn!("Trigger slowing down token bucket");
@@ -308,51 +563,232 @@ impl RefreshRunner {
         log::info!("RefreshRunner stopped");
     }
 
-    async fn try_refresh(&mut self, user: db::User, live_pn: i32) {
-        if !self.token.try_once() {
-            if self.token.is_need_log() {
-                log::info!(
-                    "Canceled refresh uid {} live_pn {} for no token",
-                    user.id(),
-                    live_pn
-                );
+    /// Maintenance runs only while refreshing slowly, at most once per configured interval.
+    fn is_maintenance_due(&self) -> bool {
+        if !matches!(self.evtx.borrow().status.0, RefreshStatus::Slow) {
+            return false;
+        }
+        let last = db::Maintenance::last().map(|r| r.started).unwrap_or(0);
+        let interval = config::get().maintenance.interval_hours * 3600;
+        chrono::Utc::now().timestamp() - last >= interval
+    }
+
+    async fn execute(&mut self, cmd: Command) -> Outcome {
+        match cmd {
+            Command::Refresh(uid) => self.try_refresh(db::User::new(uid)).await,
+            Command::Follow(enable, uid) => {
+                let u = db::User::new(uid);
+                u.enable(enable);
+                if enable {
+                    self.try_refresh(u).await
+                } else {
+                    Outcome::Done
+                }
+            }
+            Command::Activate => {
+                log::info!("Command Activate force token bucket high speed");
+                Outcome::Done
+            }
+            Command::ForceSilence(flag) => {
+                log::info!("Command ForceSilence {}", flag);
+                if flag {
+                    self.on_remote_api_err("Forced");
+                } else {
+                    self.on_remote_api_ok();
+                    self.token.available_now();
+                }
+                self.status_change(RefreshStatus::Fast);
+                Outcome::Done
+            }
+            Command::Shutdown | Command::Acked { .. } => {
+                log::error!("Command {:?} can not be executed here", cmd);
+                Outcome::Failed(String::from("unexpected command"))
             }
-            return;
         }
-        if live_pn == 0 {
-            let id = user.id();
-            match self.refresh(user.clone()).await {
-                Ok(_) => self.on_remote_api_ok(),
-                Err(e) => {
-                    user.force_upd_ctime();
+    }
+
+    /// Save refreshes of the commands still in channel as jobs, those would be lost on shutdown.
+    fn save_left_commands(&mut self) {
+        self.receiver.close();
+        while let Ok(cmd) = self.receiver.try_recv() {
+            save_left_command(cmd);
+        }
+    }
+
+    fn take_token<T: fmt::Display>(&mut self, what: T) -> bool {
+        if self.token.try_once() {
+            return true;
+        }
+        if self.token.is_need_log() {
+            log::info!("Canceled {} for no token", what);
+        }
+        false
+    }
+
+    async fn try_refresh(&mut self, user: db::User) -> Outcome {
+        let id = user.id();
+        if !self.take_token(format_args!("refresh uid {}", id)) {
+            return Outcome::NoToken;
+        }
+        match self.refresh(user.clone()).await {
+            Ok(optional_errs) if optional_errs.is_empty() => self.on_remote_api_ok(),
+            Ok(optional_errs) => {
+                for e in optional_errs {
+                    log::warn!("Refresh uid {} optional step error(s): {:#}", id, e);
                     self.on_remote_api_err(&e);
+                }
+            }
+            Err(e) => {
+                user.force_upd_ctime();
+                if let Some(status) = account_status_of(&e) {
+                    self.on_remote_api_ok();
+                    self.on_account_status(&user, status);
+                    log::warn!("Refresh uid {} account {}: {}", id, status, e);
+                } else {
                     log::error!("Refresh uid {} error(s): {}", id, e);
+                    let why = format!("{:#}", e);
+                    self.on_remote_api_err(&e);
+                    db::RefreshJob::failed(id, &why)
+                        .map_err(|e| log::error!("Fail refresh job uid {} error(s): {}", id, e))
+                        .ok();
+                    return Outcome::Failed(why);
                 }
             }
-        } else {
-            match self.refresh_live_list(live_pn).await {
-                Ok(_) => self.on_remote_api_ok(),
+        }
+        db::RefreshJob::remove(id)
+            .map_err(|e| log::error!("Remove refresh job uid {} error(s): {}", id, e))
+            .ok();
+        Outcome::Done
+    }
+
+    async fn try_sweep(&mut self, area: sweep::LiveArea, pn: i32) -> Outcome {
+        if !self.take_token(format_args!("sweep live {:?} page {}", area, pn)) {
+            return Outcome::NoToken;
+        }
+        match self.refresh_live_list(area, pn).await {
+            Ok(_) => self.on_remote_api_ok(),
+            Err(e) => {
+                log::error!("Request live {:?} page {} error(s): {}", area, pn, e);
+                let why = format!("{:#}", e);
+                self.on_remote_api_err(&e);
+                return Outcome::Failed(why);
+            }
+        }
+        Outcome::Done
+    }
+
+    async fn try_poll_lives(&mut self, interval: Duration) -> Outcome {
+        let batch = match self.live.next_batch() {
+            Ok(b) => b,
+            Err(e) => {
+                log::error!("Database query live room users error(s): {}", e);
+                return Outcome::Failed(e.to_string());
+            }
+        };
+        if batch.uids.is_empty() {
+            return Outcome::Done;
+        }
+        if !self.take_token(format_args!("poll {} live rooms", batch.uids.len())) {
+            return Outcome::NoToken;
+        }
+        match self.remote.room_status(&batch.uids).await {
+            Ok(data) => {
+                self.on_remote_api_ok();
+                self.on_room_status(live::RoomStatus::parse_all(&data), &batch, interval);
+                Outcome::Done
+            }
+            Err(e) => {
+                log::error!("Poll {} live rooms error(s): {}", batch.uids.len(), e);
+                let why = format!("{:#}", e);
+                self.on_remote_api_err(&e);
+                Outcome::Failed(why)
+            }
+        }
+    }
+
+    fn on_room_status(
+        &self,
+        rooms: Vec<live::RoomStatus>,
+        batch: &live::Batch,
+        interval: Duration,
+    ) {
+        let now = Local::now().timestamp();
+        let mut latency = self.evtx.borrow().live_latency.clone();
+        latency.bound_secs = interval.as_secs() as i64 * batch.rounds as i64;
+        for room in rooms {
+            let u = db::User::new(room.uid);
+            let mut info = match u.info() {
+                Ok(v) => v,
                 Err(e) => {
-                    self.on_remote_api_err(&e);
-                    log::error!("Request vtb live page {} error(s): {}", live_pn, e);
+                    log::trace!("uid {} not in db: {}", room.uid, e);
+                    continue;
+                }
+            };
+            let was_live = matches!(info.live_open, Some(true));
+            if room.live && !was_live {
+                self.on_new_live(&info);
+                if room.live_time > 0 && batch.priority.contains(&room.uid) {
+                    let secs = (now - room.live_time).max(0);
+                    log::info!("uid {} live start detected after {} secs", room.uid, secs);
+                    latency.record(room.uid, secs);
                 }
             }
+            if let (true, Some((parent, area, name))) = (room.live, &room.area) {
+                db::LiveAreaHit::hit(room.uid, *parent, *area, name)
+                    .map_err(|e| log::error!("Record live area hit error(s): {}", e))
+                    .ok();
+            }
+            if !room.live && info.live_open == Some(false) {
+                continue;
+            }
+            info.live_open = Some(room.live);
+            if room.live {
+                info.live_room_title = Some(room.title);
+                info.live_entropy = Some(room.online);
+            }
+            u.set_live(&info);
         }
+        self.event_change(move |ev| ev.live_latency = latency.clone());
     }
 
-    async fn refresh(&mut self, user: db::User) -> Result<()> {
+    /// Refresh _user_ , failures of the optional steps are returned, which do not fail the
+    /// refresh but slow down the coming ones.
+    async fn refresh(&mut self, user: db::User) -> Result<Vec<anyhow::Error>> {
         let last_info = user.info();
 
-        let api = self.api.user(user.id());
-        let info: db::UserInfo = api.info().await?.try_into()?;
+        let raw = self.remote.user_info(user.id()).await?;
+        let banned = matches!(raw["silence"].as_i64(), Some(1));
+        let info: db::UserInfo = raw.try_into()?;
         user.set_info(&info);
-        let videos: db::VideoVector = api.latest_videos().await?.try_into()?;
+        let raw = self.remote.latest_videos(user.id()).await?;
+        let video_count = raw["page"]["count"].as_i64();
+        let videos: db::VideoVector = raw.try_into()?;
         user.update_videos(videos.iter());
+        let mut optional_errs = vec![];
+        if let Err(e) = self.record_stat(&user, &info, video_count).await {
+            optional_errs.push(e);
+        }
+        if config::get().dynamics {
+            if let Err(e) = self.refresh_dynamics(&user, &info).await {
+                optional_errs.push(e);
+            }
+        }
+        if banned {
+            self.on_account_status(&user, db::SyncStatus::Banned);
+        } else {
+            user.set_status(db::SyncStatus::Active);
+        }
 
         let uid = user.id();
         log::info!("Refresh ok uid {}", uid);
+        let filters = db::FilterMeta::all()
+            .map_err(|e| log::error!("Count filters error(s): {}", e))
+            .ok();
         self.event_change(|ev| {
             ev.done_refresh = Some(uid);
+            if let Some(f) = &filters {
+                ev.filters = f.clone();
+            }
             if matches!(ev.status.0, RefreshStatus::Silence(_, _)) {
                 ev.status.0 = RefreshStatus::Slow;
                 ev.status_desc = ev.status.to_string();
@@ -368,18 +804,59 @@ impl RefreshRunner {
         {
             self.on_new_live(&info);
         }
+        if let Ok(last) = last_info {
+            if last.name != info.name && user.get_sync().map(|s| s.enable).unwrap_or(false) {
+                self.on_rename(&last, &info);
+            }
+        }
+
+        Ok(optional_errs)
+    }
 
+    /// Follower count is optional for a refresh, stats are recorded without it on failure.
+    async fn record_stat(
+        &self,
+        user: &db::User,
+        info: &db::UserInfo,
+        videos: Option<i64>,
+    ) -> Result<()> {
+        let followers = self.remote.follower_count(user.id()).await;
+        let live_viewers = match info.live_open {
+            Some(true) => info.live_entropy,
+            _ => None,
+        };
+        user.record_stat(followers.as_ref().ok().copied(), videos, live_viewers);
+        followers
+            .map(drop)
+            .map_err(|e| e.context(format!("fetch follower count of {}", user.id())))
+    }
+
+    /// Dynamics are optional for a refresh.
+    async fn refresh_dynamics(&self, user: &db::User, info: &db::UserInfo) -> Result<()> {
+        let uid = user.id();
+        let dynamics: db::DynamicVector = self
+            .remote
+            .dynamics(uid)
+            .await
+            .and_then(TryInto::try_into)
+            .map_err(|e| e.context(format!("fetch dynamics of {}", uid)))?;
+        let known = user.dynamics(0, 1).map(|d| !d.is_empty()).unwrap_or(false);
+        let new = user.update_dynamics(dynamics.iter());
+        if !known || new.is_empty() {
+            return Ok(());
+        }
+        if let Some(latest) = new.iter().max_by_key(|d| d.timestamp) {
+            self.on_new_dynamic(info, latest, new.len());
+        }
         Ok(())
     }
 
-    async fn refresh_live_list(&self, live_pn: i32) -> Result<()> {
+    async fn refresh_live_list(&self, area: sweep::LiveArea, live_pn: i32) -> Result<()> {
         if live_pn <= 0 {
             return Ok(());
         }
 
-        // Vup/all
-        let xlive = self.api.xlive(9, 0);
-        let v = xlive.list(live_pn.into()).await?;
+        let v = self.remote.live_list(area, live_pn).await?;
         let l = match v["list"].as_array() {
             Some(l) => l,
             None => {
@@ -387,7 +864,7 @@ impl RefreshRunner {
                 return Ok(());
             }
         };
-        log::info!("xlive get page {}", live_pn);
+        log::info!("xlive {:?} get page {}", area, live_pn);
         for i in l.iter() {
             if let Err(e) = self.try_parse_livelist_row(i) {
                 log::error!("try parse livelist row error: {}", e);
@@ -426,7 +903,7 @@ impl RefreshRunner {
             info.live_entropy = Some(online);
         }
         log::trace!("update live info from livelist row for {}", uid);
-        u.set_info(&info);
+        u.set_live(&info);
         Ok(())
     }
 
@@ -451,8 +928,23 @@ impl RefreshRunner {
         });
     }
 
+    fn policy_change(&self, policy: &policy::Policy) {
+        if self.evtx.borrow().status.1.as_ref() == Some(policy) {
+            return;
+        }
+        log::info!("Refresh policy changed to {:?}", policy);
+        let policy = policy.clone();
+        self.event_change(move |ev| {
+            ev.status.1 = Some(policy.clone());
+            ev.status_desc = ev.status.to_string();
+        });
+    }
+
     fn on_new_live(&self, info: &db::UserInfo) {
         log::info!("uid {} live open: {}", info.id, info.name);
+        if db::User::new(info.id).muted() {
+            return;
+        }
         let n = info.name.to_string();
         self.event_change(move |ev| {
             ev.new_live_msgid += 1;
@@ -460,6 +952,47 @@ impl RefreshRunner {
         });
     }
 
+    fn on_new_dynamic(&self, info: &db::UserInfo, latest: &db::DynamicInfo, count: usize) {
+        log::info!("uid {} {} new dynamic(s): {}", info.id, count, info.name);
+        if db::User::new(info.id).muted() {
+            return;
+        }
+        let (uid, name, text) = (info.id, info.name.to_string(), latest.text.to_string());
+        self.event_change(move |ev| {
+            ev.new_dynamic_msgid += 1;
+            ev.new_dynamic_uid = uid;
+            ev.new_dynamic_name = name.clone();
+            ev.new_dynamic_text = text.clone();
+        });
+    }
+
+    fn on_rename(&self, last: &db::UserInfo, info: &db::UserInfo) {
+        log::info!("uid {} renamed: {} -> {}", info.id, last.name, info.name);
+        let (uid, from, to) = (info.id, last.name.to_string(), info.name.to_string());
+        self.event_change(move |ev| {
+            ev.rename_msgid += 1;
+            ev.rename_uid = uid;
+            ev.rename_from = from.clone();
+            ev.rename_to = to.clone();
+        });
+    }
+
+    fn on_account_status(&self, user: &db::User, status: db::SyncStatus) {
+        user.set_status(status);
+        let uid = user.id();
+        match user.get_sync() {
+            Ok(sync) if sync.status_cnt >= db::SYNC_STATUS_CONFIRM_TH => log::warn!(
+                "uid {} confirmed {} {} times, stop auto refresh",
+                uid,
+                status,
+                sync.status_cnt
+            ),
+            Ok(_) => (),
+            Err(e) => log::error!("Get usersync uid {} error(s): {}", uid, e),
+        }
+        self.event_change(|ev| ev.done_refresh = Some(uid));
+    }
+
     fn on_remote_api_err<T: ToString>(&mut self, reason: T) {
         self.silence_cnt += 1;
         let why = reason.to_string();
@@ -481,6 +1014,46 @@ impl RefreshRunner {
     }
 }
 
+/// Save _cmd_ which will not be run before shutdown as a refresh job, if it is a refresh.
+fn save_left_command(cmd: Command) {
+    let (cmd, ack) = cmd.split_ack();
+    if let Command::Follow(enable, uid) = cmd {
+        db::User::new(uid).enable(enable);
+    }
+    let outcome = match cmd.job() {
+        Some((uid, kind)) => save_job(uid, kind),
+        None => {
+            log::warn!("Drop command {:?} on shutdown", cmd);
+            Outcome::Failed(String::from("engine shutting down"))
+        }
+    };
+    if let Some((_, ack)) = ack {
+        ack.send(outcome).ok();
+    }
+}
+
+fn save_job(uid: i64, kind: db::JobKind) -> Outcome {
+    match db::RefreshJob::push(uid, kind) {
+        Ok(_) => {
+            log::info!("Saved {:?} refresh job uid {} waiting for token", kind, uid);
+            Outcome::Queued
+        }
+        Err(e) => {
+            log::error!("Save refresh job uid {} error(s): {}", uid, e);
+            Outcome::NoToken
+        }
+    }
+}
+
+/// Recognise remote replies which mean the account itself is gone, i.e. code -404,
+/// those should not be counted as api failures.
+fn account_status_of(e: &anyhow::Error) -> Option<db::SyncStatus> {
+    match e.downcast_ref::<remote::ReplyError>() {
+        Some(r) if r.code == remote::CODE_NOT_FOUND => Some(db::SyncStatus::Missing),
+        _ => None,
+    }
+}
+
 fn to_datetime(i: Instant) -> DateTime<Local> {
     let now = Instant::now();
     if let Some(d) = i.checked_duration_since(now) {
@@ -0,0 +1,141 @@
+//! Cheap live status polling of followed users with known live rooms, batched by uids and
+//! scheduled apart from profile refreshes. Users of the priority filter are in every batch as
+//! far as the batch size allows, which bounds their live start latency.
+use crate::{db, Result};
+use serde_derive::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct LivePollConfig {
+    pub interval_secs: u64,
+    pub batch: usize,
+    /// Users in this filter are polled first, default 特别关注.
+    pub fid: i64,
+}
+
+impl Default for LivePollConfig {
+    fn default() -> Self {
+        Self {
+            interval_secs: 60,
+            batch: 50,
+            fid: 1,
+        }
+    }
+}
+
+/// Live start latency of priority users, from `live_time` reported by the room to detection.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+pub struct LiveLatency {
+    /// Upper bound of the polling period of priority users.
+    pub bound_secs: i64,
+    pub last_uid: Option<i64>,
+    pub last_secs: i64,
+    pub max_secs: i64,
+    pub samples: i64,
+}
+
+impl LiveLatency {
+    pub fn record(&mut self, uid: i64, secs: i64) {
+        self.last_uid = Some(uid);
+        self.last_secs = secs;
+        self.max_secs = self.max_secs.max(secs);
+        self.samples += 1;
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RoomStatus {
+    pub uid: i64,
+    pub live: bool,
+    pub title: String,
+    pub online: i64,
+    pub live_time: i64,
+    /// Live area as `(parent_id, area_id, name)` , if given.
+    pub area: Option<(i64, i64, String)>,
+}
+
+impl RoomStatus {
+    /// Parse `data` of room status reply, which is keyed by uid and empty if no room found.
+    pub fn parse_all(data: &serde_json::Value) -> Vec<Self> {
+        let rooms = match data.as_object() {
+            Some(d) => d,
+            None => return vec![],
+        };
+        rooms
+            .values()
+            .filter_map(|r| {
+                Some(Self {
+                    uid: r["uid"].as_i64()?,
+                    live: r["live_status"].as_i64() == Some(1),
+                    title: r["title"].as_str().unwrap_or_default().to_string(),
+                    online: r["online"].as_i64().unwrap_or(0),
+                    live_time: r["live_time"].as_i64().unwrap_or(0),
+                    area: match (r["area_v2_parent_id"].as_i64(), r["area_v2_id"].as_i64()) {
+                        (Some(parent), Some(area)) if parent > 0 => Some((
+                            parent,
+                            area,
+                            r["area_v2_name"].as_str().unwrap_or_default().to_string(),
+                        )),
+                        _ => None,
+                    },
+                })
+            })
+            .collect()
+    }
+}
+
+pub struct Batch {
+    pub uids: Vec<i64>,
+    pub priority: Vec<i64>,
+    /// Rounds needed to poll every priority user once.
+    pub rounds: usize,
+}
+
+pub struct LivePoll {
+    pub config: LivePollConfig,
+    cursor: usize,
+    priority_cursor: usize,
+}
+
+fn take_rotating(v: &[i64], cursor: &mut usize, n: usize) -> Vec<i64> {
+    if v.is_empty() {
+        return vec![];
+    }
+    let n = n.min(v.len());
+    let start = *cursor % v.len();
+    *cursor = (start + n) % v.len();
+    v.iter().cycle().skip(start).take(n).copied().collect()
+}
+
+impl LivePoll {
+    pub fn new(config: LivePollConfig) -> Self {
+        Self {
+            config,
+            cursor: 0,
+            priority_cursor: 0,
+        }
+    }
+
+    pub fn next_batch(&mut self) -> Result<Batch> {
+        let size = self.config.batch.max(1);
+        let (priority, others): (Vec<_>, Vec<_>) = db::User::live_room_users(self.config.fid)?
+            .into_iter()
+            .partition(|(_, pri)| *pri);
+        let priority: Vec<i64> = priority.into_iter().map(|(uid, _)| uid).collect();
+        let others: Vec<i64> = others.into_iter().map(|(uid, _)| uid).collect();
+        let rounds = priority.len().div_ceil(size);
+        let priority = take_rotating(&priority, &mut self.priority_cursor, size);
+        let mut uids = priority.clone();
+        uids.extend(take_rotating(
+            &others,
+            &mut self.cursor,
+            size - priority.len(),
+        ));
+        Ok(Batch {
+            uids,
+            priority,
+            rounds: rounds.max(1),
+        })
+    }
+}
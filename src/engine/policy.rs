@@ -0,0 +1,148 @@
+//! Time-of-day refresh policies consulted by the refresh runner on every auto refresh tick.
+use crate::i18n::{self, Locale};
+use chrono::NaiveTime;
+use serde_derive::{Deserialize, Serialize};
+use std::fmt;
+
+/// A refresh policy active between _from_ and _to_ (local `HH:MM`, may wrap over midnight),
+/// all day if they are equal, as they are when both omitted. An unnamed policy is described
+/// as the default one.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Policy {
+    pub name: String,
+    pub from: String,
+    pub to: String,
+    /// No auto refresh at all, commands and queued jobs still run.
+    pub quiet: bool,
+    /// Multiplier of the auto refresh interval.
+    pub pace: f32,
+    /// Share of auto refresh ticks which sweep live pages instead of refreshing an user.
+    pub live_share: f32,
+}
+
+impl Default for Policy {
+    fn default() -> Self {
+        Self {
+            name: String::new(),
+            from: String::from("00:00"),
+            to: String::from("00:00"),
+            quiet: false,
+            pace: 1.0,
+            live_share: 0.5,
+        }
+    }
+}
+
+impl Policy {
+    /// Description in _locale_ .
+    pub fn describe(&self, locale: Locale) -> String {
+        let name = match self.name.as_str() {
+            "" => locale.text("policy_default"),
+            name => name,
+        };
+        if self.quiet {
+            return i18n::fmt(locale.text("policy_quiet"), &[&name]);
+        }
+        let mut s = name.to_string();
+        if (self.pace - 1.0).abs() > f32::EPSILON {
+            s += &i18n::fmt(locale.text("policy_pace"), &[&self.pace]);
+        }
+        let share = format!("{:.0}", self.live_share * 100.0);
+        s + &i18n::fmt(locale.text("policy_live_share"), &[&share])
+    }
+}
+
+impl fmt::Display for Policy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.describe(Locale::current()))
+    }
+}
+
+pub struct Schedule {
+    policies: Vec<(NaiveTime, NaiveTime, Policy)>,
+    fallback: Policy,
+}
+
+fn parse_time(s: &str) -> Option<NaiveTime> {
+    NaiveTime::parse_from_str(s, "%H:%M")
+        .map_err(|e| log::error!("Invalid policy time {:?}: {}", s, e))
+        .ok()
+}
+
+impl Schedule {
+    pub fn new(policies: &[Policy]) -> Self {
+        Self {
+            policies: policies
+                .iter()
+                .filter_map(|p| {
+                    let (from, to) = (parse_time(&p.from)?, parse_time(&p.to)?);
+                    let mut p = p.clone();
+                    p.pace = p.pace.max(0.1);
+                    p.live_share = p.live_share.clamp(0.0, 1.0);
+                    Some((from, to, p))
+                })
+                .collect(),
+            fallback: Default::default(),
+        }
+    }
+
+    /// The first policy covering _now_ , or the default one.
+    pub fn active(&self, now: NaiveTime) -> &Policy {
+        self.policies
+            .iter()
+            .find(|(from, to, _)| {
+                if from == to {
+                    true
+                } else if from < to {
+                    *from <= now && now < *to
+                } else {
+                    *from <= now || now < *to
+                }
+            })
+            .map(|(_, _, p)| p)
+            .unwrap_or(&self.fallback)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(name: &str, from: &str, to: &str) -> Policy {
+        Policy {
+            name: name.to_string(),
+            from: from.to_string(),
+            to: to.to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn at(hm: &str) -> NaiveTime {
+        parse_time(hm).unwrap()
+    }
+
+    #[test]
+    fn active_policies() {
+        let schedule = Schedule::new(&[
+            policy("night", "23:00", "07:00"),
+            policy("noon", "12:00", "13:00"),
+        ]);
+        assert_eq!(schedule.active(at("23:30")).name, "night");
+        assert_eq!(schedule.active(at("06:59")).name, "night");
+        assert_eq!(schedule.active(at("12:00")).name, "noon");
+        assert_eq!(schedule.active(at("13:00")).name, "");
+    }
+
+    #[test]
+    fn equal_bounds_cover_all_day() {
+        let schedule = Schedule::new(&[policy("all", "08:00", "08:00")]);
+        assert_eq!(schedule.active(at("07:59")).name, "all");
+        assert_eq!(schedule.active(at("08:00")).name, "all");
+        let schedule = Schedule::new(&[Policy {
+            name: String::from("unbounded"),
+            ..Default::default()
+        }]);
+        assert_eq!(schedule.active(at("15:00")).name, "unbounded");
+    }
+}
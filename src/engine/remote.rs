@@ -0,0 +1,142 @@
+//! Remote apis used by the engine, every request to bilibili goes through [`Remote`].
+use super::sweep::LiveArea;
+use crate::Result;
+use anyhow::anyhow;
+use serde_json::Value;
+use std::fmt;
+
+pub const ROOM_STATUS_URL: &str =
+    "https://api.live.bilibili.com/room/v1/Room/get_status_info_by_uids";
+pub const RELATION_STAT_URL: &str = "https://api.bilibili.com/x/relation/stat";
+pub const SPACE_DYNAMICS_URL: &str = "https://api.bilibili.com/x/polymer/web-dynamic/v1/feed/space";
+pub const USER_CARD_URL: &str = "https://api.bilibili.com/x/web-interface/card";
+/// Reply code of things which do not exist, e.g. a deleted account.
+pub const CODE_NOT_FOUND: i64 = -404;
+/// Images are requested as from bilibili pages, the CDN refuses foreign referers.
+pub const IMAGE_REFERER: &str = "https://www.bilibili.com/";
+pub const USER_AGENT: &str =
+    "Mozilla/5.0 (X11; Linux x86_64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/114.0 Safari/537.36";
+
+/// A reply with non-zero _code_ .
+#[derive(Debug)]
+pub struct ReplyError {
+    pub code: i64,
+    pub message: String,
+}
+
+impl fmt::Display for ReplyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "code {}: {}", self.code, self.message)
+    }
+}
+
+impl std::error::Error for ReplyError {}
+
+pub struct Remote {
+    api: bilibili_api_rs::Client,
+    http: reqwest::Client,
+}
+
+impl Default for Remote {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Remote {
+    pub fn new() -> Self {
+        Self {
+            api: bilibili_api_rs::Client::new(),
+            http: reqwest::Client::builder()
+                .user_agent(USER_AGENT)
+                .build()
+                .unwrap_or_else(|e| {
+                    log::error!("Build http client error(s): {}, use default", e);
+                    reqwest::Client::new()
+                }),
+        }
+    }
+
+    /// Info of _uid_ , a [`ReplyError`] if the card api also fails with a reply code.
+    pub async fn user_info(&self, uid: i64) -> Result<Value> {
+        match self.api.user(uid).info().await {
+            Ok(v) => Ok(v),
+            // errors of the client carry no reply code, take it from the card api
+            Err(e) => match self.user_card(uid).await {
+                Err(card) if card.is::<ReplyError>() => Err(card.context(format!("{}", e))),
+                _ => Err(e.into()),
+            },
+        }
+    }
+
+    pub async fn user_card(&self, uid: i64) -> Result<Value> {
+        let req = self.http.get(USER_CARD_URL).query(&[("mid", uid)]);
+        Self::data_of(req).await
+    }
+
+    pub async fn latest_videos(&self, uid: i64) -> Result<Value> {
+        Ok(self.api.user(uid).latest_videos().await?)
+    }
+
+    pub async fn live_list(&self, area: LiveArea, pn: i32) -> Result<Value> {
+        let xlive = self.api.xlive(area.parent_id.into(), area.area_id.into());
+        Ok(xlive.list(pn.into()).await?)
+    }
+
+    /// Live room status of _uids_ , `data` keyed by uid.
+    pub async fn room_status(&self, uids: &[i64]) -> Result<Value> {
+        let req = self
+            .http
+            .post(ROOM_STATUS_URL)
+            .json(&serde_json::json!({ "uids": uids }));
+        Self::data_of(req).await
+    }
+
+    pub async fn follower_count(&self, uid: i64) -> Result<i64> {
+        let req = self.http.get(RELATION_STAT_URL).query(&[("vmid", uid)]);
+        let data = Self::data_of(req).await?;
+        data["follower"]
+            .as_i64()
+            .ok_or_else(|| anyhow!("relation stat follower not found"))
+    }
+
+    /// Latest dynamics in space of _uid_ .
+    pub async fn dynamics(&self, uid: i64) -> Result<Value> {
+        let req = self
+            .http
+            .get(SPACE_DYNAMICS_URL)
+            .query(&[("host_mid", uid)]);
+        Self::data_of(req).await
+    }
+
+    /// Image at _url_ with its content type.
+    pub async fn image(&self, url: &str) -> Result<(String, Vec<u8>)> {
+        let res = self
+            .http
+            .get(url)
+            .header(reqwest::header::REFERER, IMAGE_REFERER)
+            .send()
+            .await?
+            .error_for_status()?;
+        let mime = res
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or("application/octet-stream")
+            .to_string();
+        Ok((mime, res.bytes().await?.to_vec()))
+    }
+
+    async fn data_of(req: reqwest::RequestBuilder) -> Result<Value> {
+        let mut v: Value = req.send().await?.json().await?;
+        match v["code"].as_i64() {
+            Some(0) => Ok(v["data"].take()),
+            Some(code) => Err(ReplyError {
+                code,
+                message: v["message"].as_str().unwrap_or_default().to_string(),
+            }
+            .into()),
+            None => Err(anyhow!("no code in reply: {}", v)),
+        }
+    }
+}
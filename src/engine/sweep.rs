@@ -0,0 +1,145 @@
+//! Live page sweeps over configured live areas and the areas followed users were found living
+//! in, each area is picked in proportion to its hits.
+use crate::db;
+use rand::seq::SliceRandom;
+use serde_derive::{Deserialize, Serialize};
+
+/// Areas learned from hits are swept up to so many pages.
+pub const LEARNED_AREA_PAGES: i32 = 5;
+/// Reload learned hits after so many sweeps.
+pub const RELOAD_SWEEPS: usize = 100;
+
+/// A live area of `xlive` list, `area_id` 0 means all areas of the parent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub struct LiveArea {
+    pub parent_id: i32,
+    pub area_id: i32,
+    pub pages: i32,
+}
+
+impl Default for LiveArea {
+    /// Vup/all
+    fn default() -> Self {
+        Self {
+            parent_id: 9,
+            area_id: 0,
+            pages: 20,
+        }
+    }
+}
+
+struct AreaCursor {
+    area: LiveArea,
+    weight: f32,
+    pages: Vec<i32>,
+    i: usize,
+}
+
+impl AreaCursor {
+    fn new(area: LiveArea, weight: f32) -> Self {
+        let mut pages: Vec<i32> = (1..=area.pages.max(1)).collect();
+        pages.shuffle(&mut rand::thread_rng());
+        Self {
+            area,
+            weight,
+            pages,
+            i: 0,
+        }
+    }
+
+    fn next_page(&mut self) -> i32 {
+        let pn = self.pages[self.i];
+        self.i += 1;
+        if self.i >= self.pages.len() {
+            self.i = 0;
+        }
+        pn
+    }
+}
+
+pub struct LiveSweep {
+    configured: Vec<LiveArea>,
+    cursors: Vec<AreaCursor>,
+    sweeps: usize,
+}
+
+impl LiveSweep {
+    pub fn new(configured: &[LiveArea]) -> Self {
+        let mut sweep = Self {
+            configured: configured.to_vec(),
+            cursors: Default::default(),
+            sweeps: 0,
+        };
+        sweep.reload();
+        sweep
+    }
+
+    /// Weight every configured area by 1 plus its hits, and add learned areas by their hits.
+    fn reload(&mut self) {
+        let hits = db::LiveAreaHit::all()
+            .map_err(|e| log::error!("Load live area hits error(s): {}", e))
+            .unwrap_or_default();
+        let hits_of = |area: &LiveArea| -> i64 {
+            hits.iter()
+                .filter(|h| {
+                    h.parent_id == area.parent_id as i64
+                        && (area.area_id == 0 || h.area_id == area.area_id as i64)
+                })
+                .map(|h| h.hits)
+                .sum()
+        };
+        let mut cursors: Vec<AreaCursor> = self
+            .configured
+            .iter()
+            .map(|a| AreaCursor::new(*a, 1.0 + hits_of(a) as f32))
+            .collect();
+        for h in hits.iter() {
+            let covered = self.configured.iter().any(|a| {
+                a.parent_id as i64 == h.parent_id
+                    && (a.area_id == 0 || a.area_id as i64 == h.area_id)
+            });
+            if !covered && h.hits > 0 {
+                let area = LiveArea {
+                    parent_id: h.parent_id as i32,
+                    area_id: h.area_id as i32,
+                    pages: LEARNED_AREA_PAGES,
+                };
+                cursors.push(AreaCursor::new(area, h.hits as f32));
+            }
+        }
+        // keep page cursors of areas still swept
+        for c in cursors.iter_mut() {
+            if let Some(old) = self.cursors.iter().find(|o| o.area == c.area) {
+                c.pages = old.pages.clone();
+                c.i = old.i;
+            }
+        }
+        log::info!(
+            "live sweep areas: {:?}",
+            cursors
+                .iter()
+                .map(|c| (c.area, c.weight))
+                .collect::<Vec<_>>()
+        );
+        self.cursors = cursors;
+    }
+
+    /// Next area and page to sweep, the area is chosen by _dice_ in `[0, 1)` .
+    pub fn next(&mut self, dice: f32) -> Option<(LiveArea, i32)> {
+        self.sweeps += 1;
+        if self.sweeps >= RELOAD_SWEEPS {
+            self.sweeps = 0;
+            self.reload();
+        }
+        let total: f32 = self.cursors.iter().map(|c| c.weight).sum();
+        let mut point = dice * total;
+        let n = self.cursors.len();
+        for (i, c) in self.cursors.iter_mut().enumerate() {
+            if point < c.weight || i + 1 == n {
+                return Some((c.area, c.next_page()));
+            }
+            point -= c.weight;
+        }
+        None
+    }
+}
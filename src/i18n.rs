@@ -0,0 +1,203 @@
+//! Message catalogs of the UI, one JSON object of key to text per locale in `assets/i18n/` .
+//! Texts may hold positional placeholders `{0}` , `{1}` ... filled by [`fmt`] .
+//!
+//! The locale of a web request is the configured `locale` , or else the best one of its
+//! `Accept-Language` , see [`LOCALE`] . Out of any request, e.g. in the engine, the configured
+//! one or the default zh-CN is used.
+use crate::config;
+use serde_derive::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Locale {
+    #[serde(rename = "zh-CN")]
+    ZhCn,
+    #[serde(rename = "en")]
+    En,
+}
+
+impl Default for Locale {
+    fn default() -> Self {
+        Self::ZhCn
+    }
+}
+
+type Catalog = HashMap<String, String>;
+
+lazy_static::lazy_static! {
+    static ref CATALOGS: HashMap<Locale, Catalog> = [
+        (Locale::ZhCn, include_str!("../assets/i18n/zh-CN.json")),
+        (Locale::En, include_str!("../assets/i18n/en.json")),
+    ]
+    .iter()
+    .map(|(locale, text)| {
+        let catalog = serde_json::from_str(text)
+            .unwrap_or_else(|e| panic!("Parse catalog {} error(s): {}", locale.tag(), e));
+        (*locale, catalog)
+    })
+    .collect();
+}
+
+tokio::task_local! {
+    /// Locale of the web request being served.
+    pub static LOCALE: Locale;
+}
+
+impl Locale {
+    pub fn tag(&self) -> &'static str {
+        match self {
+            Self::ZhCn => "zh-CN",
+            Self::En => "en",
+        }
+    }
+
+    /// Any `zh` or `en` language tag, region and script ignored.
+    pub fn parse(tag: &str) -> Option<Self> {
+        let lang = tag
+            .trim()
+            .split(|c| c == '-' || c == '_')
+            .next()?
+            .to_ascii_lowercase();
+        match lang.as_str() {
+            "zh" => Some(Self::ZhCn),
+            "en" => Some(Self::En),
+            _ => None,
+        }
+    }
+
+    /// The supported language most preferred by an `Accept-Language` header.
+    pub fn from_accept_language(header: &str) -> Option<Self> {
+        let mut langs: Vec<(f32, Self)> = header
+            .split(',')
+            .filter_map(|item| {
+                let mut parts = item.split(';');
+                let locale = Self::parse(parts.next()?)?;
+                let q = parts
+                    .find_map(|p| p.trim().strip_prefix("q="))
+                    .and_then(|q| q.parse().ok())
+                    .unwrap_or(1.0);
+                Some((q, locale))
+            })
+            .collect();
+        // stable, so the first listed wins among equal weights
+        langs.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap_or(std::cmp::Ordering::Equal));
+        langs.first().map(|(_, locale)| *locale)
+    }
+
+    fn configured() -> Option<Self> {
+        config::get().locale.as_deref().and_then(Self::parse)
+    }
+
+    /// Locale of a request with _accept_language_ header.
+    pub fn of_request(accept_language: Option<&str>) -> Self {
+        Self::configured()
+            .or_else(|| accept_language.and_then(Self::from_accept_language))
+            .unwrap_or_default()
+    }
+
+    /// Locale of the request being served, or the configured one.
+    pub fn current() -> Self {
+        LOCALE
+            .try_with(|locale| *locale)
+            .unwrap_or_else(|_| Self::configured().unwrap_or_default())
+    }
+
+    pub fn catalog(&self) -> &'static Catalog {
+        &CATALOGS[self]
+    }
+
+    /// Text of _key_ , falling back to zh-CN and then the key itself.
+    pub fn text<'a>(&self, key: &'a str) -> &'a str {
+        self.catalog()
+            .get(key)
+            .or_else(|| Self::ZhCn.catalog().get(key))
+            .map(String::as_str)
+            .unwrap_or(key)
+    }
+
+    /// Name of filter _fid_ , the built-in ones are translated unless renamed by the user.
+    pub fn filter_name<'a>(&self, fid: i64, name: &'a str) -> &'a str {
+        // names seeded by old versions end with a stray backslash
+        match (fid, name.trim_end_matches('\\')) {
+            (0, "全部") => self.text("filter_all"),
+            (1, "特别关注") => self.text("filter_special"),
+            _ => name,
+        }
+    }
+}
+
+/// Fill placeholders `{0}` , `{1}` ... of _text_ with _args_ .
+pub fn fmt(text: &str, args: &[&dyn std::fmt::Display]) -> String {
+    args.iter()
+        .enumerate()
+        .fold(text.to_string(), |s, (i, arg)| {
+            s.replace(&format!("{{{}}}", i), &arg.to_string())
+        })
+}
+
+/// Template filter `filter_name(fid=0)` , see [`Locale::filter_name`] .
+pub fn tera_filter_name(
+    value: &tera::Value,
+    args: &HashMap<String, tera::Value>,
+) -> tera::Result<tera::Value> {
+    let name = tera::try_get_value!("filter_name", "value", String, value);
+    let fid = args
+        .get("fid")
+        .and_then(|v| v.as_i64())
+        .ok_or_else(|| tera::Error::msg("filter_name requires a `fid`"))?;
+    let name = Locale::current().filter_name(fid, &name).to_string();
+    Ok(tera::Value::String(name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn locale_parses_tags() {
+        assert_eq!(Locale::parse("zh"), Some(Locale::ZhCn));
+        assert_eq!(Locale::parse("zh-TW"), Some(Locale::ZhCn));
+        assert_eq!(Locale::parse("zh_Hans_CN"), Some(Locale::ZhCn));
+        assert_eq!(Locale::parse(" EN-us "), Some(Locale::En));
+        assert_eq!(Locale::parse("ja"), None);
+        assert_eq!(Locale::parse(""), None);
+    }
+
+    #[test]
+    fn locale_of_accept_language() {
+        let best = Locale::from_accept_language;
+        assert_eq!(best("en-US,en;q=0.9,zh-CN;q=0.8"), Some(Locale::En));
+        assert_eq!(best("ja,en;q=0.5,zh;q=0.7"), Some(Locale::ZhCn));
+        assert_eq!(best("en;q=0.5, zh;q=0.5"), Some(Locale::En));
+        assert_eq!(best("zh;q=bad"), Some(Locale::ZhCn));
+        assert_eq!(best("ja, fr;q=0.9"), None);
+        assert_eq!(best(""), None);
+    }
+
+    #[test]
+    fn catalogs_have_same_keys() {
+        let mut zh: Vec<_> = Locale::ZhCn.catalog().keys().collect();
+        let mut en: Vec<_> = Locale::En.catalog().keys().collect();
+        zh.sort();
+        en.sort();
+        assert_eq!(zh, en);
+    }
+
+    #[test]
+    fn text_falls_back() {
+        assert_eq!(Locale::En.text("confirm"), "Confirm");
+        assert_eq!(Locale::ZhCn.text("confirm"), "确认");
+        assert_eq!(Locale::En.text("no_such_key"), "no_such_key");
+        assert_eq!(fmt("{1} of {0}, {1}", &[&"a", &2]), "2 of a, 2");
+        assert_eq!(fmt("{0} {2}", &[&"a"]), "a {2}");
+    }
+
+    #[test]
+    fn filter_names_of_builtins() {
+        let en = Locale::En;
+        assert_eq!(en.filter_name(0, "全部"), en.text("filter_all"));
+        assert_eq!(en.filter_name(1, "特别关注\\"), en.text("filter_special"));
+        assert_eq!(en.filter_name(1, "renamed"), "renamed");
+        assert_eq!(en.filter_name(2, "全部"), "全部");
+    }
+}
@@ -13,8 +13,10 @@ macro_rules! var_path {
     };
 }
 
+pub mod config;
 pub mod db;
 pub mod engine;
+pub mod i18n;
 pub mod www;
 
 pub fn prepare_log() -> Result<()> {
@@ -6,6 +6,66 @@ async fn main() {
         panic!("Error at startup: {}", e);
     }
 
+    let args: Vec<String> = std::env::args().collect();
+    match args.get(1).map(String::as_str) {
+        Some("maintain") => {
+            let code = match engine::maintain().await {
+                Ok(report) => {
+                    println!(
+                        "{}",
+                        serde_json::to_string_pretty(&report).expect("Serialize report failure")
+                    );
+                    0
+                }
+                Err(e) => {
+                    eprintln!("Database maintenance error(s): {}", e);
+                    1
+                }
+            };
+            db::blocking_shutdown();
+            std::process::exit(code);
+        }
+        Some("backup") => {
+            let code = match engine::backup().await {
+                Ok(file) => {
+                    println!("Backed up to {} ({} bytes)", file.name, file.bytes);
+                    0
+                }
+                Err(e) => {
+                    eprintln!("Database backup error(s): {}", e);
+                    1
+                }
+            };
+            db::blocking_shutdown();
+            std::process::exit(code);
+        }
+        Some("restore") => {
+            // The database must not be opened here, it is about to be replaced.
+            let from = match args.get(2).map(std::path::Path::new) {
+                Some(from) => from,
+                None => {
+                    eprintln!("Usage: {} restore <backup file>", args[0]);
+                    std::process::exit(2);
+                }
+            };
+            // A bare file name is looked up in the backup directory.
+            let from = if from.exists() {
+                from.to_path_buf()
+            } else {
+                engine::backups().dir.join(from)
+            };
+            match engine::restore(from.clone()).await {
+                Ok(_) => println!("Restored from {}", from.display()),
+                Err(e) => {
+                    eprintln!("Database restore error(s): {}", e);
+                    std::process::exit(1);
+                }
+            }
+            return;
+        }
+        _ => (),
+    }
+
     if let Err(e) = main_loop().await {
         panic!("Error at main_loop: {}", e);
     }
@@ -1,6 +1,7 @@
 use crate::{
-    db,
-    engine::{self, Command},
+    db::{self, FilterMeta, RefreshJob, UserInfo, UserInfoHistory, VideoInfo},
+    engine::{self, Command, Outcome},
+    i18n::{self, Locale},
 };
 use anyhow::Result;
 use chrono::{TimeZone, Utc};
@@ -8,42 +9,34 @@ use futures::StreamExt;
 use serde_derive::{Deserialize, Serialize};
 use std::convert::From;
 use std::convert::Infallible;
-use tera::{Context as TeraContext, Tera};
+use tera::Context as TeraContext;
 use tokio::sync::oneshot;
 use tokio_stream::wrappers::WatchStream;
-use warp::{http::StatusCode, sse::Event, Filter};
-
-lazy_static::lazy_static! {
-    pub static ref TEMPLATES: Tera = {
-        match Tera::new("templates/**/*.html") {
-            Ok(t) => t,
-            Err(e) => {
-                log::error!("Parsing error(s): {}", e);
-                ::std::process::exit(1);
-            }
-        }
-    };
-}
+use utoipa::{IntoParams, ToSchema};
+use warp::{
+    http::StatusCode,
+    hyper::{self, service::Service},
+    reply::{Json, WithStatus},
+    sse::Event,
+    Filter, Rejection, Reply,
+};
 
 macro_rules! render {
     (@errhtml $kind:expr, $reason:expr) => {
-        warp::reply::html(render!(@err TEMPLATES, $kind, $reason))
+        warp::reply::html(render!(@err $kind, $reason))
     };
-    (@err $tera:ident, $kind:expr, $reason:expr) => {
+    (@err $kind:expr, $reason:expr) => {
         {
             let mut ctx = TeraContext::new();
             ctx.insert("kind", $kind);
             ctx.insert("reason", $reason);
-            $tera.render("failure.html", &ctx).unwrap()
+            assets::render("failure.html", &ctx).unwrap()
         }
     };
 
     ($name:expr, $ctx:expr) => {
-        render!(TEMPLATES, $name, $ctx)
-    };
-    ($tera:ident, $name:expr, $ctx:expr) => {
-        warp::reply::html($tera.render($name, $ctx).unwrap_or_else(|e|
-            render!(@err $tera, "Tera engine", &format!("Error: tera: {}", e))
+        warp::reply::html(assets::render($name, $ctx).unwrap_or_else(|e|
+            render!(@err "Tera engine", &format!("Error: tera: {}", e))
         ))
     };
 }
@@ -117,42 +110,157 @@ macro_rules! jsnapi {
     };
 }
 
-#[derive(Debug, Deserialize, Serialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, Clone, ToSchema)]
 struct FollowOptions {
     enable: bool,
     uid: i64,
 }
 
-#[derive(Debug, Deserialize, Serialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, Clone, ToSchema)]
 struct RefreshOptions {
     uid: i64,
 }
 
-#[derive(Debug, Deserialize, Serialize, Clone)]
+/// With _wait_ the reply is the outcome of the refresh instead of `success` ; with _queue_ a
+/// refresh lacking token waits for one instead of being dropped.
+#[derive(Debug, Deserialize, Serialize, Clone, Default, IntoParams)]
+#[into_params(parameter_in = Query)]
+struct RefreshQuery {
+    #[serde(default)]
+    wait: bool,
+    #[serde(default)]
+    queue: bool,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, ToSchema)]
+struct CancelJobOptions {
+    uid: i64,
+}
+
+/// Replace note and tags of _uid_ .
+#[derive(Debug, Deserialize, Serialize, Clone, ToSchema)]
+struct NoteOptions {
+    uid: i64,
+    note: String,
+    tags: Vec<String>,
+}
+
+/// Mute _uid_ for _secs_ , non-positive _secs_ unmutes. With _pause_ the user is not auto
+/// refreshed while muted.
+#[derive(Debug, Deserialize, Serialize, Clone, ToSchema)]
+struct MuteOptions {
+    uid: i64,
+    secs: i64,
+    #[serde(default)]
+    pause: bool,
+}
+
+/// Remove everything recorded of unfollowed _uid_ .
+#[derive(Debug, Deserialize, Serialize, Clone, ToSchema)]
+struct PurgeOptions {
+    uid: i64,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Default)]
+struct SearchQuery {
+    #[serde(default)]
+    q: String,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, ToSchema)]
 struct ForceSilenceOptions {
     silence: bool,
 }
 
-#[derive(Debug, Deserialize, Serialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, Clone, ToSchema)]
 struct ModFilterOptions {
     uid: i64,
     fid: i64,
     priority: i64,
 }
 
-#[derive(Debug, Deserialize, Serialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, Clone, ToSchema)]
+struct FilterQueryOptions {
+    fid: i64,
+    query: db::UserQuerySpec,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, ToSchema)]
 struct NewFilterOptions {
     name: String,
+    /// Saved query of a smart filter.
+    #[serde(default)]
+    query: Option<db::UserQuerySpec>,
+}
+
+/// Change state of videos _vids_ , or of all videos of _uid_ which are in state _from_ .
+#[derive(Debug, Deserialize, Serialize, Clone, ToSchema)]
+struct VideoStateOptions {
+    #[serde(default)]
+    vids: Vec<String>,
+    uid: Option<i64>,
+    #[serde(default)]
+    from: db::VideoState,
+    state: db::VideoState,
 }
 
 macro_rules! req_type {
-    (@post) => {
-        warp::post()
-            .and(warp::body::content_length_limit(1024 * 16))
-            .and(warp::body::json())
+    (@post $op:literal) => {
+        warp::post().and(probe($op)).and(req_type!(@json))
+    };
+    (@json) => {
+        warp::body::content_length_limit(1024 * 16).and(warp::body::json())
     };
 }
 
+/// Header of requests which [`probe`] stops before the handler, in tests.
+#[cfg(test)]
+const PROBE_HEADER: &str = "x-route-probe";
+
+/// Rejection of a probing request by the route of documented operation `.0` .
+#[cfg(test)]
+#[derive(Debug)]
+struct RouteProbe(&'static str);
+
+#[cfg(test)]
+impl warp::reject::Reject for RouteProbe {}
+
+#[cfg(test)]
+lazy_static::lazy_static! {
+    /// Operations of the routes built so far.
+    static ref PROBED: std::sync::Mutex<std::collections::BTreeSet<&'static str>> =
+        Default::default();
+}
+
+/// Mounted right after the method of the route of documented operation _op_ , as
+/// `"METHOD /path/{param}"` . In tests, a request with [`PROBE_HEADER`] is rejected there
+/// with a [`RouteProbe`] , so that routes are checked without running their handlers.
+#[cfg(test)]
+fn probe(op: &'static str) -> impl Filter<Extract = (), Error = Rejection> + Clone {
+    PROBED
+        .lock()
+        .unwrap_or_else(|e| panic!("Probed routes access error(s): {}", e))
+        .insert(op);
+    warp::header::optional::<String>(PROBE_HEADER)
+        .and_then(move |probe: Option<String>| async move {
+            match probe {
+                Some(_) => Err(warp::reject::custom(RouteProbe(op))),
+                None => Ok(()),
+            }
+        })
+        .untuple_one()
+}
+
+#[cfg(not(test))]
+fn probe(_op: &'static str) -> impl Filter<Extract = (), Error = Infallible> + Clone {
+    warp::any()
+}
+
+mod api;
+mod assets;
+mod img;
+mod openapi;
+
 macro_rules! reply_json_result {
     (@err $e:expr, $c:expr) => {
         warp::reply::with_status(warp::reply::json(&format!("Err: {}", $e)), $c)
@@ -178,6 +286,22 @@ struct UserExt {
     new_video_tsrepr: String,
     live_entropy: String,
     ctimestamp: i64,
+    sync_status: db::SyncStatus,
+    sync_status_tsrepr: String,
+    sync_paused: bool,
+    unseen: i64,
+    note: db::UserNote,
+    mute_until_tsrepr: Option<String>,
+    enabled: bool,
+}
+
+fn tsrepr(ts: i64) -> String {
+    Utc.timestamp_opt(ts, 0)
+        .latest()
+        .unwrap_or(chrono::DateTime::<Utc>::MIN_UTC)
+        .with_timezone(&chrono::Local)
+        .format("%Y-%m-%d %H:%M:%S")
+        .to_string()
 }
 
 #[derive(Debug, Serialize)]
@@ -207,19 +331,32 @@ impl From<Result<db::UserInfo>> for UserPack {
                         }),
                         new_video_ts: sync.as_ref().map(|s| s.new_video_ts).unwrap_or(0),
                         ctimestamp: sync.as_ref().map(|s| s.ctimestamp).unwrap_or(0),
+                        sync_status: sync.as_ref().map(|s| s.status).unwrap_or_default(),
+                        sync_status_tsrepr: sync
+                            .as_ref()
+                            .map(|s| tsrepr(s.status_ts))
+                            .unwrap_or_default(),
+                        sync_paused: sync
+                            .as_ref()
+                            .map(|s| {
+                                s.status != db::SyncStatus::Active
+                                    && s.status_cnt >= db::SYNC_STATUS_CONFIRM_TH
+                            })
+                            .unwrap_or(false),
+                        unseen: db::User::new(data.id).unseen_count().unwrap_or(0),
+                        note: db::User::new(data.id).note().unwrap_or_default(),
+                        mute_until_tsrepr: sync
+                            .as_ref()
+                            .ok()
+                            .filter(|s| s.mute_until > Utc::now().timestamp())
+                            .map(|s| tsrepr(s.mute_until)),
+                        enabled: sync.as_ref().map(|s| s.enable).unwrap_or(false),
                         new_video_title: sync
                             .as_ref()
                             .map(|s| s.new_video_title.clone())
                             .unwrap_or_else(|_| String::default()),
                         new_video_tsrepr: sync
-                            .map(|s| {
-                                Utc.timestamp_opt(s.new_video_ts, 0)
-                                    .latest()
-                                    .unwrap_or(chrono::DateTime::<Utc>::MIN_UTC)
-                                    .with_timezone(&chrono::Local)
-                                    .format("%Y-%m-%d %H:%M:%S")
-                                    .to_string()
-                            })
+                            .map(|s| tsrepr(s.new_video_ts))
                             .unwrap_or_else(|_| String::default()),
                         live_entropy: match data.live_entropy {
                             Some(e @ 0..=1000) => format!("{}", e),
@@ -243,6 +380,13 @@ impl From<Result<db::UserInfo>> for UserPack {
                     new_video_tsrepr: Default::default(),
                     live_entropy: Default::default(),
                     ctimestamp: 0,
+                    sync_status: Default::default(),
+                    sync_status_tsrepr: Default::default(),
+                    sync_paused: false,
+                    unseen: 0,
+                    note: Default::default(),
+                    mute_until_tsrepr: None,
+                    enabled: false,
                 },
                 data: db::UserInfo {
                     name: format!("Err: {}", e),
@@ -254,6 +398,141 @@ impl From<Result<db::UserInfo>> for UserPack {
     }
 }
 
+#[derive(Debug, Serialize)]
+struct HistoryPack {
+    data: db::UserInfoHistory,
+    tsrepr: String,
+}
+
+impl From<db::UserInfoHistory> for HistoryPack {
+    fn from(data: db::UserInfoHistory) -> Self {
+        Self {
+            tsrepr: if data.timestamp > 0 {
+                tsrepr(data.timestamp)
+            } else {
+                String::from("-")
+            },
+            data,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct VideoPack {
+    data: db::StatedVideo,
+    link: String,
+    tsrepr: String,
+    owner: String,
+}
+
+impl From<db::StatedVideo> for VideoPack {
+    fn from(data: db::StatedVideo) -> Self {
+        Self {
+            link: format!("https://www.bilibili.com/video/{}", data.video.vid),
+            tsrepr: tsrepr(data.video.utime.timestamp()),
+            owner: db::User::new(data.uid)
+                .info()
+                .map(|i| i.name)
+                .unwrap_or_else(|_| data.uid.to_string()),
+            data,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct DynamicPack {
+    data: db::DynamicInfo,
+    link: String,
+    tsrepr: String,
+    owner: String,
+}
+
+impl From<db::DynamicInfo> for DynamicPack {
+    fn from(data: db::DynamicInfo) -> Self {
+        Self {
+            link: format!("https://t.bilibili.com/{}", data.did),
+            tsrepr: tsrepr(data.timestamp),
+            owner: db::User::new(data.uid)
+                .info()
+                .map(|i| i.name)
+                .unwrap_or_else(|_| data.uid.to_string()),
+            data,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct JobPack {
+    data: db::RefreshJob,
+    name: String,
+    due_tsrepr: String,
+}
+
+impl From<db::RefreshJob> for JobPack {
+    fn from(data: db::RefreshJob) -> Self {
+        Self {
+            name: db::User::new(data.uid)
+                .info()
+                .map(|i| i.name)
+                .unwrap_or_else(|_| data.uid.to_string()),
+            due_tsrepr: tsrepr(data.not_before),
+            data,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct FilterMember {
+    meta: db::FilterMeta,
+    member: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct UserPage {
+    user: UserPack,
+    sync: Option<db::UserSync>,
+    ctimerepr: String,
+    videos: Vec<VideoPack>,
+    dynamics: Vec<DynamicPack>,
+    filters: Vec<FilterMember>,
+    history: Vec<HistoryPack>,
+}
+
+impl UserPage {
+    pub fn of(uid: i64) -> Result<Self> {
+        let u = db::User::new(uid);
+        let sync = u.get_sync().ok();
+        let joined = u.filters()?;
+        Ok(Self {
+            user: UserPack::from(u.info()),
+            ctimerepr: sync
+                .as_ref()
+                .map(|s| tsrepr(s.ctimestamp))
+                .unwrap_or_default(),
+            sync,
+            videos: u
+                .videos(0, i64::MAX)?
+                .into_iter()
+                .map(VideoPack::from)
+                .collect(),
+            dynamics: u
+                .dynamics(0, 30)?
+                .into_iter()
+                .map(DynamicPack::from)
+                .collect(),
+            filters: db::FilterMeta::all()?
+                .into_iter()
+                .filter(|f| f.fid > 0)
+                .map(|meta| FilterMember {
+                    member: joined.iter().any(|j| j.fid == meta.fid),
+                    meta,
+                })
+                .collect(),
+            history: u.history()?.into_iter().map(HistoryPack::from).collect(),
+        })
+    }
+}
+
 #[derive(Debug, Serialize)]
 struct IndexData {
     status: String,
@@ -267,73 +546,467 @@ impl IndexData {
     }
 }
 
-fn sse_ev_engine(e: engine::Event) -> std::result::Result<Event, Infallible> {
+/// The stream outlives the request, so _locale_ is taken at its start.
+fn sse_ev_engine(mut e: engine::Event, locale: Locale) -> std::result::Result<Event, Infallible> {
+    e.status_desc = e.status.describe(locale);
     Ok(Event::default()
         .json_data(e)
         .expect("engine event json-stringify should never fail"))
 }
 
-pub async fn run(shutdown: oneshot::Receiver<i32>) {
-    let _running = engine::will_shutdown();
+#[utoipa::path(
+    post,
+    path = "/op/follow",
+    request_body = FollowOptions,
+    responses((status = 200, description = "Follow command sent", body = String))
+)]
+fn op_follow(opt: FollowOptions) -> warp::reply::Json {
+    log::debug!("op_follow arg: {:?}", opt);
+    jsnapi!(@cmd Command::Follow(opt.enable, opt.uid).queued())
+}
+
+#[utoipa::path(
+    post,
+    path = "/op/refresh",
+    params(RefreshQuery),
+    request_body = RefreshOptions,
+    responses(
+        (status = 200, description = "`success` once sent, or the outcome if waited", body = Outcome),
+    )
+)]
+async fn op_refresh(query: RefreshQuery, opt: RefreshOptions) -> warp::reply::Json {
+    let cmd = Command::Refresh(opt.uid);
+    if query.wait {
+        warp::reply::json(&engine::ask(cmd, query.queue).await)
+    } else if query.queue {
+        jsnapi!(engine::ask(cmd, true).await)
+    } else {
+        jsnapi!(@cmd cmd)
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/op/silence",
+    request_body = ForceSilenceOptions,
+    responses((status = 200, description = "Silence command sent", body = String))
+)]
+fn op_silence(opt: ForceSilenceOptions) -> warp::reply::Json {
+    jsnapi!(@cmd Command::ForceSilence(opt.silence))
+}
+
+#[utoipa::path(
+    post,
+    path = "/op/job/cancel",
+    request_body = CancelJobOptions,
+    responses((status = 200, description = "`success` or the error", body = String))
+)]
+fn op_cancel_job(opt: CancelJobOptions) -> warp::reply::Json {
+    jsnapi!(@try db::RefreshJob::remove(opt.uid); e; {
+        log::error!("cancel refresh job error(s): {}", e);
+        format!("Db error: {}", e)
+    })
+}
+
+#[utoipa::path(
+    post,
+    path = "/op/maintain",
+    responses(
+        (status = 200, description = "Maintenance report", body = MaintenanceReport),
+        (status = 500, description = "`Err: ...` string", body = String),
+    )
+)]
+async fn op_maintain() -> WithStatus<Json> {
+    reply_json_result!(engine::maintain().await)
+}
+
+#[utoipa::path(
+    post,
+    path = "/op/backup",
+    responses(
+        (status = 200, description = "The new backup", body = BackupFile),
+        (status = 500, description = "`Err: ...` string", body = String),
+    )
+)]
+async fn op_backup() -> WithStatus<Json> {
+    reply_json_result!(engine::backup().await)
+}
+
+#[utoipa::path(
+    post,
+    path = "/op/mute",
+    request_body = MuteOptions,
+    responses((status = 200, description = "`success` or the error", body = String))
+)]
+fn op_mute(opt: MuteOptions) -> warp::reply::Json {
+    let until = if opt.secs > 0 {
+        Utc::now().timestamp() + opt.secs
+    } else {
+        0
+    };
+    jsnapi!(@try db::User::new(opt.uid).mute(until, opt.pause); e; {
+        log::error!("mute error(s): {}", e);
+        format!("Db error: {}", e)
+    })
+}
+
+#[utoipa::path(
+    post,
+    path = "/op/purge",
+    request_body = PurgeOptions,
+    responses((status = 200, description = "`success` or the error", body = String))
+)]
+fn op_purge(opt: PurgeOptions) -> warp::reply::Json {
+    jsnapi!(@try db::User::new(opt.uid).purge(); e; {
+        log::error!("purge error(s): {}", e);
+        format!("Error: {}", e)
+    })
+}
+
+#[utoipa::path(
+    post,
+    path = "/op/note",
+    request_body = NoteOptions,
+    responses((status = 200, description = "`success` or the error", body = String))
+)]
+fn op_note(opt: NoteOptions) -> warp::reply::Json {
+    jsnapi!(@try db::User::new(opt.uid).set_note(&opt.note, &opt.tags); e; {
+        log::error!("set note error(s): {}", e);
+        format!("Db error: {}", e)
+    })
+}
+
+#[utoipa::path(
+    post,
+    path = "/op/mod/filter",
+    request_body = ModFilterOptions,
+    responses((status = 200, description = "Membership modified", body = String))
+)]
+fn op_mod_filter(opt: ModFilterOptions) -> warp::reply::Json {
+    db::User::new(opt.uid).mod_filter(opt.fid, opt.priority);
+    jsnapi!(@ok)
+}
 
+#[utoipa::path(
+    post,
+    path = "/op/new/filter",
+    request_body = NewFilterOptions,
+    responses((status = 200, description = "`success` or the error", body = String))
+)]
+fn op_new_filter(opt: NewFilterOptions) -> warp::reply::Json {
+    jsnapi!(@try db::FilterMeta::new(&opt.name, opt.query); e; {
+        log::error!("new filter error(s): {}", e);
+        format!("Db error: {}", e)
+    })
+}
+
+#[utoipa::path(
+    post,
+    path = "/op/filter/query",
+    request_body = FilterQueryOptions,
+    responses((status = 200, description = "`success` or the error", body = String))
+)]
+fn op_filter_query(opt: FilterQueryOptions) -> warp::reply::Json {
+    jsnapi!(@try db::FilterMeta::set_query(opt.fid, &opt.query); e; {
+        log::error!("set filter query error(s): {}", e);
+        format!("Error: {}", e)
+    })
+}
+
+#[utoipa::path(
+    post,
+    path = "/op/video/state",
+    request_body = VideoStateOptions,
+    responses((status = 200, description = "`success` or the error", body = String))
+)]
+fn op_video_state(opt: VideoStateOptions) -> warp::reply::Json {
+    let r = match opt.uid {
+        Some(uid) => db::User::new(uid).mark_videos(opt.from, opt.state),
+        None => db::VideoInfo::mark(&opt.vids, opt.state),
+    };
+    jsnapi!(@try r; e; {
+        log::error!("mark video state error(s): {}", e);
+        format!("Db error: {}", e)
+    })
+}
+
+#[utoipa::path(
+    get,
+    path = "/get/user/{uid}",
+    params(("uid" = i64, Path, description = "User id")),
+    responses(
+        (status = 200, description = "User info", body = UserInfo),
+        (status = 500, description = "`Err: ...` string", body = String),
+    )
+)]
+fn get_user(uid: i64) -> WithStatus<Json> {
+    reply_json_result!(db::User::new(uid).info())
+}
+
+#[utoipa::path(
+    get,
+    path = "/get/vlist/{uid}",
+    params(("uid" = i64, Path, description = "User id")),
+    responses(
+        (status = 200, description = "Recent 30 videos", body = [VideoInfo]),
+        (status = 500, description = "`Err: ...` string", body = String),
+    )
+)]
+fn get_vlist(uid: i64) -> WithStatus<Json> {
+    reply_json_result!(db::User::new(uid).recent_videos(30))
+}
+
+#[utoipa::path(
+    get,
+    path = "/get/flist",
+    responses(
+        (status = 200, description = "All filters with counts", body = [FilterMeta]),
+        (status = 500, description = "`Err: ...` string", body = String),
+    )
+)]
+fn get_flist() -> WithStatus<Json> {
+    reply_json_result!(db::FilterMeta::all())
+}
+
+#[utoipa::path(
+    get,
+    path = "/get/jobs",
+    responses(
+        (status = 200, description = "Pending refresh jobs, most urgent first", body = [RefreshJob]),
+        (status = 500, description = "`Err: ...` string", body = String),
+    )
+)]
+fn get_jobs() -> WithStatus<Json> {
+    reply_json_result!(db::RefreshJob::all())
+}
+
+#[utoipa::path(
+    get,
+    path = "/get/history/{uid}",
+    params(("uid" = i64, Path, description = "User id")),
+    responses(
+        (status = 200, description = "Recorded profiles, latest first", body = [UserInfoHistory]),
+        (status = 500, description = "`Err: ...` string", body = String),
+    )
+)]
+fn get_history(uid: i64) -> WithStatus<Json> {
+    reply_json_result!(db::User::new(uid).history())
+}
+
+#[utoipa::path(
+    get,
+    path = "/get/note/{uid}",
+    params(("uid" = i64, Path, description = "User id")),
+    responses(
+        (status = 200, description = "Note and tags, empty if never annotated", body = UserNote),
+        (status = 500, description = "`Err: ...` string", body = String),
+    )
+)]
+fn get_note(uid: i64) -> WithStatus<Json> {
+    reply_json_result!(db::User::new(uid).note())
+}
+
+#[utoipa::path(
+    get,
+    path = "/get/stats/{uid}",
+    params(("uid" = i64, Path, description = "User id")),
+    responses(
+        (status = 200, description = "Stats samples, oldest first", body = [UserStat]),
+        (status = 500, description = "`Err: ...` string", body = String),
+    )
+)]
+fn get_stats(uid: i64) -> WithStatus<Json> {
+    reply_json_result!(db::User::new(uid).stats(0))
+}
+
+#[utoipa::path(
+    get,
+    path = "/list/{fid}/{order}/{start}/{len}",
+    params(
+        ("fid" = i64, Path, description = "Filter id, 0 for all"),
+        ("order" = String, Path, description = "Comma separated `key[:asc|:desc]`, key is one of `default`, `video`, `live`, `unseen`, `growth`, `name`, `refresh`, `follow`, `unfollow`, `priority` or `live_start`"),
+        ("start" = i64, Path, description = "Offset"),
+        ("len" = i64, Path, description = "Limit"),
+    ),
+    responses(
+        (status = 200, description = "User ids", body = [i64]),
+        (status = 500, description = "`Err: ...` string", body = String),
+    )
+)]
+fn list(fid: i64, typ: String, start: i64, len: i64) -> WithStatus<Json> {
+    match typ.parse() {
+        Ok(order) => reply_json_result!(db::User::list(fid, order, start, len)),
+        Err(e) => reply_json_result!(@err e, StatusCode::BAD_REQUEST),
+    }
+}
+
+fn routes() -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
     let index = warp::path::end().map(|| {
         let mut ctx = TeraContext::new();
         ctx.insert("data", &IndexData::now());
         render!("index.html", &ctx)
     });
 
+    let user_page = warp::path!("user" / i64).map(|uid| {
+        let mut ctx = TeraContext::new();
+        ctx.insert("page", &www_try!(@db UserPage::of(uid)));
+        async_command!(Command::Activate);
+        render!("user.html", &ctx)
+    });
+
+    let later_page = warp::path!("later").map(|| {
+        let videos: Vec<VideoPack> =
+            www_try!(@db db::VideoInfo::list_state(db::VideoState::Saved, 0, i64::MAX))
+                .into_iter()
+                .map(VideoPack::from)
+                .collect();
+        let mut ctx = TeraContext::new();
+        ctx.insert("videos", &videos);
+        render!("later.html", &ctx)
+    });
+
+    let img = warp::path!("img" / String)
+        .and(warp::get())
+        .then(img::serve);
+
+    let jobs_page = warp::path!("jobs").map(|| {
+        let jobs: Vec<JobPack> = www_try!(@db db::RefreshJob::all())
+            .into_iter()
+            .map(JobPack::from)
+            .collect();
+        let mut ctx = TeraContext::new();
+        ctx.insert("jobs", &jobs);
+        render!("jobs.html", &ctx)
+    });
+
     let op_follow = warp::path!("follow")
-        .and(req_type!(@post))
-        .map(|opt: FollowOptions| {
-            log::debug!("op_follow arg: {:?}", opt);
-            jsnapi!(@cmd Command::Follow(opt.enable, opt.uid))
-        });
+        .and(req_type!(@post "POST /op/follow"))
+        .map(op_follow);
     let op_refresh = warp::path!("refresh")
-        .and(req_type!(@post))
-        .map(|opt: RefreshOptions| jsnapi!(@cmd Command::Refresh(opt.uid)));
+        .and(warp::post())
+        .and(probe("POST /op/refresh"))
+        .and(warp::query::<RefreshQuery>())
+        .and(req_type!(@json))
+        .then(op_refresh);
     let op_silence = warp::path!("silence")
-        .and(req_type!(@post))
-        .map(|opt: ForceSilenceOptions| jsnapi!(@cmd Command::ForceSilence(opt.silence)));
-    let op_mod_filter =
-        warp::path!("mod" / "filter")
-            .and(req_type!(@post))
-            .map(|opt: ModFilterOptions| {
-                db::User::new(opt.uid).mod_filter(opt.fid, opt.priority);
-                jsnapi!(@ok)
-            });
-    let op_new_filter =
-        warp::path!("new" / "filter")
-            .and(req_type!(@post))
-            .map(|opt: NewFilterOptions| {
-                jsnapi!(@try db::FilterMeta::new(&opt.name); e; {
-                    log::error!("new filter error(s): {}", e);
-                    format!("Db error: {}", e)
-                })
-            });
+        .and(req_type!(@post "POST /op/silence"))
+        .map(op_silence);
+    let op_mod_filter = warp::path!("mod" / "filter")
+        .and(req_type!(@post "POST /op/mod/filter"))
+        .map(op_mod_filter);
+    let op_new_filter = warp::path!("new" / "filter")
+        .and(req_type!(@post "POST /op/new/filter"))
+        .map(op_new_filter);
+    let op_filter_query = warp::path!("filter" / "query")
+        .and(req_type!(@post "POST /op/filter/query"))
+        .map(op_filter_query);
+    let op_video_state = warp::path!("video" / "state")
+        .and(req_type!(@post "POST /op/video/state"))
+        .map(op_video_state);
+    let op_cancel_job = warp::path!("job" / "cancel")
+        .and(req_type!(@post "POST /op/job/cancel"))
+        .map(op_cancel_job);
+    let op_note = warp::path!("note")
+        .and(req_type!(@post "POST /op/note"))
+        .map(op_note);
+    let op_mute = warp::path!("mute")
+        .and(req_type!(@post "POST /op/mute"))
+        .map(op_mute);
+    let op_purge = warp::path!("purge")
+        .and(req_type!(@post "POST /op/purge"))
+        .map(op_purge);
+    let op_maintain = warp::path!("maintain")
+        .and(warp::post())
+        .and(probe("POST /op/maintain"))
+        .then(op_maintain);
+    let op_backup = warp::path!("backup")
+        .and(warp::post())
+        .and(probe("POST /op/backup"))
+        .then(op_backup);
     let op = warp::path("op");
 
-    let get_user =
-        warp::path!("user" / i64).map(|uid| reply_json_result!(db::User::new(uid).info()));
+    let get_user = warp::path!("user" / i64)
+        .and(probe("GET /get/user/{uid}"))
+        .map(get_user);
     let get_vlist = warp::path!("vlist" / i64)
-        .map(|uid| reply_json_result!(db::User::new(uid).recent_videos(30)));
-    let get_flist = warp::path!("flist").map(|| reply_json_result!(db::FilterMeta::all()));
+        .and(probe("GET /get/vlist/{uid}"))
+        .map(get_vlist);
+    let get_flist = warp::path!("flist")
+        .and(probe("GET /get/flist"))
+        .map(get_flist);
+    let get_history = warp::path!("history" / i64)
+        .and(probe("GET /get/history/{uid}"))
+        .map(get_history);
+    let get_jobs = warp::path!("jobs")
+        .and(probe("GET /get/jobs"))
+        .map(get_jobs);
+    let get_stats = warp::path!("stats" / i64)
+        .and(probe("GET /get/stats/{uid}"))
+        .map(get_stats);
+    let get_note = warp::path!("note" / i64)
+        .and(probe("GET /get/note/{uid}"))
+        .map(get_note);
     let get = warp::path("get").and(warp::get());
 
     let list = warp::path!("list" / i64 / String / i64 / i64)
         .and(warp::get())
-        .map(|fid, typ: String, start, len| {
-            reply_json_result!(db::User::list(fid, typ.as_str().into(), start, len))
-        });
-
+        .and(probe("GET /list/{fid}/{order}/{start}/{len}"))
+        .map(list);
     let card_ulist =
         warp::path!("ulist" / i64 / String / i64 / i64).map(|fid, typ: String, start, len| {
-            let uids = db::User::list(fid, typ.as_str().into(), start, len);
+            let order: db::Order =
+                www_try!(@hdl typ.parse(), e, render!(@errhtml "Order", &e.to_string()));
+            let uids = db::User::list(fid, order, start, len);
+            if start == 0 {
+                db::FilterMeta::visit(fid);
+            }
+            ulist_render!(uids, true)
+        });
+    let card_search = warp::path!("search" / i64 / i64)
+        .and(warp::query::<SearchQuery>())
+        .map(|start, len, search: SearchQuery| {
+            let uids = if search.q.trim().is_empty() {
+                Ok(vec![])
+            } else {
+                db::UserQuery {
+                    predicates: vec![db::Predicate::Text(search.q.trim().to_string())],
+                    ..Default::default()
+                }
+                .list(start, len)
+            };
             ulist_render!(uids, true)
         });
+    let card_unfollowed = warp::path!("unfollowed" / i64 / i64).map(|start, len| {
+        let uids = db::UserQuery {
+            predicates: vec![db::Predicate::Disabled],
+            order: db::Order::by(db::OrderKey::Unfollow),
+            ..Default::default()
+        }
+        .list(start, len);
+        ulist_render!(uids, true)
+    });
     let card_one = warp::path!("one" / i64).map(|uid| {
         let users = vec![UserPack::from(db::User::new(uid).info())];
         ulist_render!(@pack users, false)
     });
+    let card_history = warp::path!("history" / i64).map(|uid| {
+        let history: Vec<HistoryPack> = www_try!(@db db::User::new(uid).history())
+            .into_iter()
+            .map(HistoryPack::from)
+            .collect();
+        let mut ctx = TeraContext::new();
+        ctx.insert("history", &history);
+        render!("user_history.html", &ctx)
+    });
+    let card_dynamics = warp::path!("dynamics" / i64 / i64 / i64).map(|fid, start, len| {
+        let dynamics: Vec<DynamicPack> = www_try!(@db db::DynamicInfo::timeline(fid, start, len))
+            .into_iter()
+            .map(DynamicPack::from)
+            .collect();
+        let mut ctx = TeraContext::new();
+        ctx.insert("dynamics", &dynamics);
+        render!("dynamic_cards.html", &ctx)
+    });
     let card_filter_options = warp::path!("filter" / "options").map(|| {
         let filters = www_try!(@db db::FilterMeta::all());
         let mut ctx = TeraContext::new();
@@ -342,36 +1015,96 @@ pub async fn run(shutdown: oneshot::Receiver<i32>) {
     });
     let card = warp::path("card");
 
-    let ev_engine = warp::path!("engine").map(|| {
-        warp::sse::reply(
-            warp::sse::keep_alive().stream(WatchStream::new(engine::event_rx()).map(sse_ev_engine)),
-        )
-    });
+    let ev_engine =
+        warp::path!("engine").map(|| {
+            let locale = Locale::current();
+            warp::sse::reply(warp::sse::keep_alive().stream(
+                WatchStream::new(engine::event_rx()).map(move |e| sse_ev_engine(e, locale)),
+            ))
+        });
     let ev = warp::path("ev");
 
-    let static_files = warp::path("static").and(warp::fs::dir("./static"));
-    let favicon = warp::path!("favicon.ico").and(warp::fs::file("./static/favicon.ico"));
+    let static_files = warp::path!("static" / String)
+        .and(warp::get())
+        .and(warp::query::<assets::StaticQuery>())
+        .map(assets::serve_static);
+    let favicon = warp::path!("favicon.ico")
+        .map(|| assets::serve_static(String::from("favicon.ico"), Default::default()));
+
+    let openapi_json = warp::path!("openapi.json").map(|| warp::reply::json(&openapi::spec()));
+    let openapi_docs = warp::path!("docs").map(|| render!("api_docs.html", &TeraContext::new()));
+    let api_doc = warp::path("api").and(warp::get());
 
-    let app = index
+    index
+        .or(user_page)
+        .or(later_page)
+        .or(jobs_page)
+        .or(img)
         .or(op.and(op_follow))
         .or(op.and(op_refresh))
         .or(op.and(op_silence))
         .or(op.and(op_mod_filter))
         .or(op.and(op_new_filter))
+        .or(op.and(op_filter_query))
+        .or(op.and(op_video_state))
+        .or(op.and(op_cancel_job))
+        .or(op.and(op_note))
+        .or(op.and(op_mute))
+        .or(op.and(op_purge))
+        .or(op.and(op_maintain))
+        .or(op.and(op_backup))
         .or(get.and(get_user))
         .or(get.and(get_vlist))
         .or(get.and(get_flist))
+        .or(get.and(get_history))
+        .or(get.and(get_stats))
+        .or(get.and(get_note))
+        .or(get.and(get_jobs))
         .or(list)
         .or(static_files)
         .or(card.and(card_ulist))
+        .or(card.and(card_search))
+        .or(card.and(card_unfollowed))
         .or(card.and(card_one))
+        .or(card.and(card_history))
+        .or(card.and(card_dynamics))
         .or(card.and(card_filter_options))
         .or(ev.and(ev_engine))
-        .or(favicon);
-    log::info!("www running");
-    let (_, run) = warp::serve(app).bind_with_graceful_shutdown(([0, 0, 0, 0], 3731), async move {
-        shutdown.await.ok();
+        .or(api_doc.and(openapi_json))
+        .or(api_doc.and(openapi_docs))
+        .or(api::routes())
+        .or(favicon)
+}
+
+pub async fn run(shutdown: oneshot::Receiver<i32>) {
+    let _running = engine::will_shutdown();
+
+    let app = warp::service(routes());
+    // Every request is served within the scope of its locale.
+    let make_svc = hyper::service::make_service_fn(move |_| {
+        let app = app.clone();
+        async move {
+            Ok::<_, Infallible>(hyper::service::service_fn(
+                move |req: hyper::Request<hyper::Body>| {
+                    let locale = Locale::of_request(
+                        req.headers()
+                            .get(hyper::header::ACCEPT_LANGUAGE)
+                            .and_then(|v| v.to_str().ok()),
+                    );
+                    let mut app = app.clone();
+                    i18n::LOCALE.scope(locale, async move { app.call(req).await })
+                },
+            ))
+        }
     });
-    run.await;
+    log::info!("www running");
+    let run = hyper::Server::bind(&([0, 0, 0, 0], 3731).into())
+        .serve(make_svc)
+        .with_graceful_shutdown(async move {
+            shutdown.await.ok();
+        });
+    if let Err(e) = run.await {
+        log::error!("www server error(s): {}", e);
+    }
     log::info!("www stopped");
 }
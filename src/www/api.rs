@@ -0,0 +1,1214 @@
+//! Versioned JSON api under `/api/v1`, every reply is an [`Envelope`].
+use super::{probe, ForceSilenceOptions, NewFilterOptions, VideoStateOptions};
+use crate::{
+    db,
+    engine::{self, Command},
+};
+use chrono::Utc;
+use serde::Serialize;
+use serde_derive::{Deserialize, Serialize};
+use std::convert::{Infallible, TryFrom};
+use utoipa::{IntoParams, ToSchema};
+use warp::{
+    http::StatusCode,
+    reply::{Json, WithStatus},
+    Filter, Rejection, Reply,
+};
+
+pub const DEFAULT_PAGE_LIMIT: i64 = 20;
+pub const MAX_PAGE_LIMIT: i64 = 100;
+
+/// Uniform reply body: `data` is set on success, `error` on failure.
+#[derive(Debug, Serialize, ToSchema)]
+#[aliases(
+    UserItemEnvelope = Envelope<UserItem>,
+    UserItemPageEnvelope = Envelope<UserItemPage>,
+    StatedVideoPageEnvelope = Envelope<StatedVideoPage>,
+    HistoryEnvelope = Envelope<Vec<db::UserInfoHistory>>,
+    FilterEnvelope = Envelope<db::FilterMeta>,
+    FiltersEnvelope = Envelope<Vec<db::FilterMeta>>,
+    VideoStateEnvelope = Envelope<VideoStateOptions>,
+    EventEnvelope = Envelope<engine::Event>,
+    JobsEnvelope = Envelope<Vec<db::RefreshJob>>,
+    DynamicPageEnvelope = Envelope<DynamicPage>,
+    StatsEnvelope = Envelope<Vec<db::UserStat>>,
+    NoteEnvelope = Envelope<db::UserNote>,
+    NotesEnvelope = Envelope<Vec<db::UserNote>>,
+    PurgedEnvelope = Envelope<Purged>,
+    MaintenanceEnvelope = Envelope<db::MaintenanceReport>,
+    BackupEnvelope = Envelope<db::BackupFile>,
+    BackupsEnvelope = Envelope<Vec<db::BackupFile>>,
+    AcceptedEnvelope = Envelope<Accepted>,
+    OutcomeEnvelope = Envelope<engine::Outcome>
+)]
+pub struct Envelope<T> {
+    pub ok: bool,
+    pub data: Option<T>,
+    pub error: Option<ApiError>,
+}
+
+#[derive(Debug, Serialize, Clone, ToSchema)]
+pub struct ApiError {
+    pub code: String,
+    pub message: String,
+}
+
+/// An api error with the http status it is replied with.
+#[derive(Debug)]
+pub struct Failure {
+    pub status: StatusCode,
+    pub error: ApiError,
+}
+
+type ApiResult<T> = std::result::Result<T, Failure>;
+
+impl Failure {
+    pub fn new<T: ToString>(status: StatusCode, code: &str, message: T) -> Self {
+        Self {
+            status,
+            error: ApiError {
+                code: code.to_string(),
+                message: message.to_string(),
+            },
+        }
+    }
+
+    pub fn bad_request<T: ToString>(message: T) -> Self {
+        Self::new(StatusCode::BAD_REQUEST, "bad_request", message)
+    }
+
+    pub fn not_found<T: ToString>(message: T) -> Self {
+        Self::new(StatusCode::NOT_FOUND, "not_found", message)
+    }
+
+    pub fn engine_unavailable<T: ToString>(message: T) -> Self {
+        Self::new(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "engine_unavailable",
+            message,
+        )
+    }
+}
+
+impl From<anyhow::Error> for Failure {
+    fn from(e: anyhow::Error) -> Self {
+        match e.downcast_ref::<rusqlite::Error>() {
+            Some(rusqlite::Error::QueryReturnedNoRows) => Self::not_found(e),
+            Some(_) => Self::new(StatusCode::INTERNAL_SERVER_ERROR, "database", e),
+            None => Self::new(StatusCode::INTERNAL_SERVER_ERROR, "internal", e),
+        }
+    }
+}
+
+fn reply<T: Serialize>(status: StatusCode, r: ApiResult<T>) -> WithStatus<Json> {
+    match r {
+        Ok(data) => warp::reply::with_status(
+            warp::reply::json(&Envelope {
+                ok: true,
+                data: Some(data),
+                error: None,
+            }),
+            status,
+        ),
+        Err(f) => warp::reply::with_status(
+            warp::reply::json(&Envelope::<()> {
+                ok: false,
+                data: None,
+                error: Some(f.error),
+            }),
+            f.status,
+        ),
+    }
+}
+
+/// Query string pagination, `?offset=0&limit=20`.
+#[derive(Debug, Deserialize, Serialize, Clone, Default, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct PageQuery {
+    pub offset: Option<i64>,
+    pub limit: Option<i64>,
+}
+
+impl PageQuery {
+    fn range(&self) -> ApiResult<(i64, i64)> {
+        let offset = self.offset.unwrap_or(0);
+        let limit = self.limit.unwrap_or(DEFAULT_PAGE_LIMIT);
+        if offset < 0 {
+            return Err(Failure::bad_request("offset must not be negative"));
+        }
+        if !(1..=MAX_PAGE_LIMIT).contains(&limit) {
+            return Err(Failure::bad_request(format!(
+                "limit must be in 1..={}",
+                MAX_PAGE_LIMIT
+            )));
+        }
+        Ok((offset, limit))
+    }
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+#[aliases(
+    UserItemPage = Page<UserItem>,
+    StatedVideoPage = Page<db::StatedVideo>,
+    DynamicPage = Page<db::DynamicInfo>
+)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    pub offset: i64,
+    pub limit: i64,
+}
+
+/// `GET /api/v1/users` query, `fid` 0 stands for all followed users. Users listed by `fid`
+/// are narrowed by the other fields, see [`db::UserQuerySpec`].
+#[derive(Debug, Deserialize, Serialize, Clone, Default, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct UserListQuery {
+    pub fid: Option<i64>,
+    /// Comma separated filter ids, users in any of them.
+    pub any: Option<String>,
+    /// Comma separated filter ids, users in all of them.
+    pub all: Option<String>,
+    /// Comma separated filter ids, users in none of them.
+    pub not: Option<String>,
+    /// Comma separated predicates, see [`db::UserQuerySpec`].
+    pub is: Option<String>,
+    /// Comma separated `key[:asc|:desc]` , see [`db::Order`].
+    pub order: Option<String>,
+    pub offset: Option<i64>,
+    pub limit: Option<i64>,
+}
+
+impl UserListQuery {
+    fn query(&self) -> ApiResult<db::UserQuery> {
+        let spec = db::UserQuerySpec {
+            any: self.any.clone(),
+            all: self.all.clone(),
+            not: self.not.clone(),
+            is: self.is.clone(),
+            order: self.order.clone(),
+        };
+        let narrow = db::UserQuery::try_from(&spec).map_err(Failure::bad_request)?;
+        Ok(db::UserQuery::of_filter(self.fid.unwrap_or(0))?.and(narrow))
+    }
+
+    fn page(&self) -> PageQuery {
+        PageQuery {
+            offset: self.offset,
+            limit: self.limit,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, ToSchema)]
+pub struct FollowRequest {
+    pub uid: i64,
+}
+
+/// Mute for _secs_ , with _pause_ not auto refreshed either.
+#[derive(Debug, Deserialize, Serialize, Clone, ToSchema)]
+pub struct MuteRequest {
+    pub secs: i64,
+    #[serde(default)]
+    pub pause: bool,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, ToSchema)]
+pub struct MembershipRequest {
+    pub priority: i64,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Default, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct VideoListQuery {
+    pub state: Option<db::VideoState>,
+    pub offset: Option<i64>,
+    pub limit: Option<i64>,
+}
+
+/// `GET /api/v1/dynamics` query, `fid` 0 stands for all followed users.
+#[derive(Debug, Deserialize, Serialize, Clone, Default, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct DynamicListQuery {
+    pub fid: Option<i64>,
+    pub offset: Option<i64>,
+    pub limit: Option<i64>,
+}
+
+/// `GET /api/v1/users/{uid}/stats` query, `since` is an unix timestamp.
+#[derive(Debug, Deserialize, Serialize, Clone, Default, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct StatsQuery {
+    pub since: Option<i64>,
+}
+
+/// `POST /api/v1/users/{uid}/refresh` query: `wait` for the outcome, `queue` as a refresh job
+/// if no token is left, which is the default.
+#[derive(Debug, Deserialize, Serialize, Clone, Default, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct RefreshQuery {
+    pub wait: Option<bool>,
+    pub queue: Option<bool>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct UserItem {
+    pub info: db::UserInfo,
+    pub sync: Option<db::UserSync>,
+}
+
+impl TryFrom<i64> for UserItem {
+    type Error = Failure;
+
+    fn try_from(uid: i64) -> ApiResult<Self> {
+        let u = db::User::new(uid);
+        Ok(Self {
+            info: u.info()?,
+            sync: u.get_sync().ok(),
+        })
+    }
+}
+
+/// Rows deleted by a purge.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct Purged {
+    pub rows: usize,
+}
+
+/// Acknowledgement of a command accepted by the engine.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct Accepted {
+    pub command: String,
+}
+
+fn user_page(query: db::UserQuery, page: &PageQuery) -> ApiResult<Page<UserItem>> {
+    let (offset, limit) = page.range()?;
+    let items = query
+        .list(offset, limit)?
+        .into_iter()
+        .filter_map(|uid| UserItem::try_from(uid).ok())
+        .collect();
+    Ok(Page {
+        items,
+        offset,
+        limit,
+    })
+}
+
+/// Reply the outcome of an acknowledged command, failures as error envelopes.
+fn outcome_reply(outcome: engine::Outcome) -> WithStatus<Json> {
+    let f = match outcome {
+        engine::Outcome::Done => return reply(StatusCode::OK, Ok(outcome)),
+        engine::Outcome::Queued => return reply(StatusCode::ACCEPTED, Ok(outcome)),
+        engine::Outcome::NoToken => {
+            Failure::new(StatusCode::TOO_MANY_REQUESTS, "no_token", outcome)
+        }
+        engine::Outcome::Failed(_) => Failure::new(StatusCode::BAD_GATEWAY, "failed", outcome),
+    };
+    reply::<()>(f.status, Err(f))
+}
+
+async fn send_command(cmd: Command) -> ApiResult<Accepted> {
+    let command = match &cmd {
+        Command::Acked { cmd, .. } => format!("{:?}", cmd),
+        cmd => format!("{:?}", cmd),
+    };
+    engine::handle()
+        .send(cmd)
+        .await
+        .map_err(Failure::engine_unavailable)?;
+    Ok(Accepted { command })
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/users",
+    params(UserListQuery),
+    responses(
+        (status = 200, description = "Followed users", body = UserItemPageEnvelope),
+        (status = 400, description = "Invalid query, error envelope"),
+    )
+)]
+fn list_users(q: UserListQuery) -> WithStatus<Json> {
+    reply(
+        StatusCode::OK,
+        q.query().and_then(|query| user_page(query, &q.page())),
+    )
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/users/{uid}",
+    params(("uid" = i64, Path, description = "User id")),
+    responses(
+        (status = 200, description = "User info and sync state", body = UserItemEnvelope),
+        (status = 404, description = "Unknown user, error envelope"),
+    )
+)]
+fn get_user(uid: i64) -> WithStatus<Json> {
+    reply(StatusCode::OK, UserItem::try_from(uid))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/users/{uid}/videos",
+    params(("uid" = i64, Path, description = "User id"), PageQuery),
+    responses(
+        (status = 200, description = "Stored videos, latest first", body = StatedVideoPageEnvelope),
+        (status = 400, description = "Invalid query, error envelope"),
+    )
+)]
+fn user_videos(uid: i64, page: PageQuery) -> WithStatus<Json> {
+    reply(
+        StatusCode::OK,
+        page.range().and_then(|(offset, limit)| {
+            Ok(Page {
+                items: db::User::new(uid).videos(offset, limit)?,
+                offset,
+                limit,
+            })
+        }),
+    )
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/users/{uid}/dynamics",
+    params(("uid" = i64, Path, description = "User id"), PageQuery),
+    responses(
+        (status = 200, description = "Stored dynamics, latest first", body = DynamicPageEnvelope),
+        (status = 400, description = "Invalid query, error envelope"),
+    )
+)]
+fn user_dynamics(uid: i64, page: PageQuery) -> WithStatus<Json> {
+    reply(
+        StatusCode::OK,
+        page.range().and_then(|(offset, limit)| {
+            Ok(Page {
+                items: db::User::new(uid).dynamics(offset, limit)?,
+                offset,
+                limit,
+            })
+        }),
+    )
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/dynamics",
+    params(DynamicListQuery),
+    responses(
+        (status = 200, description = "Dynamics of followed users, latest first", body = DynamicPageEnvelope),
+        (status = 400, description = "Invalid query, error envelope"),
+    )
+)]
+fn list_dynamics(q: DynamicListQuery) -> WithStatus<Json> {
+    let page = PageQuery {
+        offset: q.offset,
+        limit: q.limit,
+    };
+    reply(
+        StatusCode::OK,
+        page.range().and_then(|(offset, limit)| {
+            Ok(Page {
+                items: db::DynamicInfo::timeline(q.fid.unwrap_or(0), offset, limit)?,
+                offset,
+                limit,
+            })
+        }),
+    )
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/users/{uid}/history",
+    params(("uid" = i64, Path, description = "User id")),
+    responses(
+        (status = 200, description = "Recorded profiles, latest first", body = HistoryEnvelope),
+    )
+)]
+fn user_history(uid: i64) -> WithStatus<Json> {
+    reply(
+        StatusCode::OK,
+        db::User::new(uid).history().map_err(Failure::from),
+    )
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/users/{uid}/stats",
+    params(("uid" = i64, Path, description = "User id"), StatsQuery),
+    responses(
+        (status = 200, description = "Follower, video and live viewer samples, oldest first", body = StatsEnvelope),
+    )
+)]
+fn user_stats(uid: i64, q: StatsQuery) -> WithStatus<Json> {
+    reply(
+        StatusCode::OK,
+        db::User::new(uid)
+            .stats(q.since.unwrap_or(0))
+            .map_err(Failure::from),
+    )
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/users/{uid}/note",
+    params(("uid" = i64, Path, description = "User id")),
+    responses(
+        (status = 200, description = "Note and tags, empty if never annotated", body = NoteEnvelope),
+    )
+)]
+fn user_note(uid: i64) -> WithStatus<Json> {
+    reply(
+        StatusCode::OK,
+        db::User::new(uid).note().map_err(Failure::from),
+    )
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/v1/users/{uid}/note",
+    params(("uid" = i64, Path, description = "User id")),
+    request_body = UserNote,
+    responses(
+        (status = 200, description = "Replaced note and tags, `uid` and `mtime` of the body are ignored", body = NoteEnvelope),
+    )
+)]
+fn set_user_note(uid: i64, note: db::UserNote) -> WithStatus<Json> {
+    reply(
+        StatusCode::OK,
+        db::User::new(uid)
+            .set_note(&note.note, &note.tags)
+            .map_err(Failure::from),
+    )
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/v1/users/{uid}/mute",
+    params(("uid" = i64, Path, description = "User id")),
+    request_body = MuteRequest,
+    responses(
+        (status = 200, description = "Muted user", body = UserItemEnvelope),
+        (status = 400, description = "Non-positive secs, error envelope"),
+        (status = 404, description = "Unfollowed user, error envelope"),
+    )
+)]
+fn mute_user(uid: i64, req: MuteRequest) -> WithStatus<Json> {
+    let r = if req.secs <= 0 {
+        Err(Failure::bad_request("secs must be positive"))
+    } else {
+        mute(uid, Utc::now().timestamp() + req.secs, req.pause)
+    };
+    reply(StatusCode::OK, r)
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/v1/users/{uid}/mute",
+    params(("uid" = i64, Path, description = "User id")),
+    responses(
+        (status = 200, description = "Unmuted user", body = UserItemEnvelope),
+        (status = 404, description = "Unfollowed user, error envelope"),
+    )
+)]
+fn unmute_user(uid: i64) -> WithStatus<Json> {
+    reply(StatusCode::OK, mute(uid, 0, false))
+}
+
+fn mute(uid: i64, until: i64, pause: bool) -> ApiResult<UserItem> {
+    db::User::new(uid)
+        .mute(until, pause)
+        .map_err(Failure::not_found)?;
+    UserItem::try_from(uid)
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/notes",
+    responses(
+        (status = 200, description = "Notes and tags of all annotated users", body = NotesEnvelope),
+    )
+)]
+fn list_notes() -> WithStatus<Json> {
+    reply(StatusCode::OK, db::UserNote::all().map_err(Failure::from))
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/v1/notes",
+    request_body = [UserNote],
+    responses(
+        (status = 200, description = "Imported notes and tags, users not listed are left alone", body = NotesEnvelope),
+        (status = 400, description = "Invalid uid, error envelope"),
+    )
+)]
+fn import_notes(notes: Vec<db::UserNote>) -> WithStatus<Json> {
+    let r = match notes.iter().find(|n| n.uid <= 0) {
+        Some(n) => Err(Failure::bad_request(format!("invalid uid {}", n.uid))),
+        None => db::UserNote::import(&notes).map_err(Failure::from),
+    };
+    reply(StatusCode::OK, r)
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/filters",
+    responses(
+        (status = 200, description = "All filters with counts", body = FiltersEnvelope),
+    )
+)]
+fn list_filters() -> WithStatus<Json> {
+    reply(StatusCode::OK, db::FilterMeta::all().map_err(Failure::from))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/filters",
+    request_body = NewFilterOptions,
+    responses(
+        (status = 201, description = "Created filter", body = FilterEnvelope),
+        (status = 400, description = "Invalid name or query, error envelope"),
+    )
+)]
+fn new_filter(opt: NewFilterOptions) -> WithStatus<Json> {
+    let invalid_query = opt
+        .query
+        .as_ref()
+        .and_then(|spec| db::UserQuery::try_from(spec).err());
+    let r = if opt.name.trim().is_empty() {
+        Err(Failure::bad_request("filter name must not be empty"))
+    } else if let Some(e) = invalid_query {
+        Err(Failure::bad_request(e))
+    } else {
+        db::FilterMeta::new(opt.name.trim(), opt.query.clone()).map_err(Failure::from)
+    };
+    reply(StatusCode::CREATED, r)
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/v1/filters/{fid}/query",
+    params(("fid" = i64, Path, description = "Filter id")),
+    request_body = UserQuerySpec,
+    responses(
+        (status = 200, description = "Smart filter with the new query", body = FilterEnvelope),
+        (status = 400, description = "Not a smart filter or invalid query, error envelope"),
+        (status = 404, description = "Unknown filter, error envelope"),
+    )
+)]
+fn set_filter_query(fid: i64, spec: db::UserQuerySpec) -> WithStatus<Json> {
+    let r = db::FilterMeta::try_from(fid)
+        .map_err(Failure::from)
+        .and_then(|_| db::FilterMeta::set_query(fid, &spec).map_err(Failure::bad_request))
+        .and_then(|_| db::FilterMeta::try_from(fid).map_err(Failure::from));
+    reply(StatusCode::OK, r)
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/filters/{fid}/users",
+    params(("fid" = i64, Path, description = "Filter id"), PageQuery),
+    responses(
+        (status = 200, description = "Members of the filter", body = UserItemPageEnvelope),
+        (status = 404, description = "Unknown filter, error envelope"),
+    )
+)]
+fn filter_users(fid: i64, page: PageQuery) -> WithStatus<Json> {
+    let r = db::FilterMeta::try_from(fid)
+        .map_err(Failure::from)
+        .and_then(|_| user_page(db::UserQuery::of_filter(fid)?, &page));
+    reply(StatusCode::OK, r)
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/v1/filters/{fid}/users/{uid}",
+    params(
+        ("fid" = i64, Path, description = "Filter id"),
+        ("uid" = i64, Path, description = "User id"),
+    ),
+    request_body = MembershipRequest,
+    responses(
+        (status = 200, description = "Joined", body = FilterEnvelope),
+        (status = 400, description = "Non-positive priority, or filter 0 is not modifiable, error envelope"),
+        (status = 404, description = "Unknown filter, error envelope"),
+    )
+)]
+fn join_filter(fid: i64, uid: i64, opt: MembershipRequest) -> WithStatus<Json> {
+    if opt.priority <= 0 {
+        let f = Failure::bad_request("priority must be positive, leave by DELETE");
+        return reply::<()>(f.status, Err(f));
+    }
+    mod_membership(fid, uid, opt.priority)
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/v1/filters/{fid}/users/{uid}",
+    params(
+        ("fid" = i64, Path, description = "Filter id"),
+        ("uid" = i64, Path, description = "User id"),
+    ),
+    responses(
+        (status = 200, description = "Left", body = FilterEnvelope),
+        (status = 400, description = "Filter 0 is not modifiable, error envelope"),
+        (status = 404, description = "Unknown filter, error envelope"),
+    )
+)]
+fn leave_filter(fid: i64, uid: i64) -> WithStatus<Json> {
+    mod_membership(fid, uid, 0)
+}
+
+fn mod_membership(fid: i64, uid: i64, priority: i64) -> WithStatus<Json> {
+    let r = db::FilterMeta::try_from(fid)
+        .map_err(Failure::from)
+        .and_then(|f| {
+            if f.fid <= 0 {
+                return Err(Failure::bad_request("filter 0 holds all users"));
+            }
+            if f.query.is_some() {
+                return Err(Failure::bad_request(
+                    "members of a smart filter are queried",
+                ));
+            }
+            db::User::new(uid).mod_filter(fid, priority);
+            Ok(f)
+        });
+    reply(StatusCode::OK, r)
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/videos",
+    params(VideoListQuery),
+    responses(
+        (status = 200, description = "Videos in state, latest marked first", body = StatedVideoPageEnvelope),
+        (status = 400, description = "Invalid query, error envelope"),
+    )
+)]
+fn list_videos(q: VideoListQuery) -> WithStatus<Json> {
+    let page = PageQuery {
+        offset: q.offset,
+        limit: q.limit,
+    };
+    let state = q.state.unwrap_or(db::VideoState::Saved);
+    reply(
+        StatusCode::OK,
+        page.range().and_then(|(offset, limit)| {
+            Ok(Page {
+                items: db::VideoInfo::list_state(state, offset, limit)?,
+                offset,
+                limit,
+            })
+        }),
+    )
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/videos/state",
+    request_body = VideoStateOptions,
+    responses(
+        (status = 200, description = "Applied change", body = VideoStateEnvelope),
+    )
+)]
+fn mark_videos(opt: VideoStateOptions) -> WithStatus<Json> {
+    let r = match opt.uid {
+        Some(uid) => db::User::new(uid).mark_videos(opt.from, opt.state),
+        None => db::VideoInfo::mark(&opt.vids, opt.state),
+    };
+    reply(StatusCode::OK, r.map(|_| opt).map_err(Failure::from))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/lives",
+    params(UserListQuery),
+    responses(
+        (status = 200, description = "Living users by popularity", body = UserItemPageEnvelope),
+        (status = 400, description = "Invalid query, error envelope"),
+    )
+)]
+fn list_lives(q: UserListQuery) -> WithStatus<Json> {
+    let live = db::UserQuery {
+        order: db::Order::by(db::OrderKey::LiveEntropy),
+        ..Default::default()
+    };
+    reply(
+        StatusCode::OK,
+        q.query()
+            .and_then(|query| user_page(query.and(live), &q.page())),
+    )
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/engine/status",
+    responses(
+        (status = 200, description = "Latest engine event", body = EventEnvelope),
+    )
+)]
+fn engine_status() -> WithStatus<Json> {
+    let mut ev = engine::event_rx().borrow().clone();
+    ev.status_desc = ev.status.to_string();
+    reply::<engine::Event>(StatusCode::OK, Ok(ev))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/jobs",
+    responses(
+        (status = 200, description = "Pending refresh jobs, most urgent first", body = JobsEnvelope),
+    )
+)]
+fn list_jobs() -> WithStatus<Json> {
+    reply(StatusCode::OK, db::RefreshJob::all().map_err(Failure::from))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/v1/jobs/{uid}",
+    params(("uid" = i64, Path, description = "User id of the job")),
+    responses(
+        (status = 200, description = "Job cancelled", body = AcceptedEnvelope),
+        (status = 404, description = "No job of the user, error envelope"),
+    )
+)]
+fn cancel_job(uid: i64) -> WithStatus<Json> {
+    reply(
+        StatusCode::OK,
+        match db::RefreshJob::remove(uid) {
+            Ok(true) => Ok(Accepted {
+                command: format!("cancel refresh job {}", uid),
+            }),
+            Ok(false) => Err(Failure::not_found(format!("no refresh job of {}", uid))),
+            Err(e) => Err(Failure::from(e)),
+        },
+    )
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/users",
+    request_body = FollowRequest,
+    responses(
+        (status = 202, description = "Follow command accepted", body = AcceptedEnvelope),
+        (status = 503, description = "Engine unavailable, error envelope"),
+    )
+)]
+async fn follow_user(opt: FollowRequest) -> WithStatus<Json> {
+    follow(true, opt.uid).await
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/v1/users/{uid}",
+    params(("uid" = i64, Path, description = "User id")),
+    responses(
+        (status = 202, description = "Unfollow command accepted", body = AcceptedEnvelope),
+        (status = 503, description = "Engine unavailable, error envelope"),
+    )
+)]
+async fn unfollow_user(uid: i64) -> WithStatus<Json> {
+    follow(false, uid).await
+}
+
+async fn follow(enable: bool, uid: i64) -> WithStatus<Json> {
+    reply(
+        StatusCode::ACCEPTED,
+        send_command(Command::Follow(enable, uid).queued()).await,
+    )
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/users/{uid}/purge",
+    params(("uid" = i64, Path, description = "User id")),
+    responses(
+        (status = 200, description = "Everything recorded of the unfollowed user removed", body = PurgedEnvelope),
+        (status = 400, description = "User still followed, error envelope"),
+        (status = 404, description = "Unknown user, error envelope"),
+    )
+)]
+fn purge_user(uid: i64) -> WithStatus<Json> {
+    let u = db::User::new(uid);
+    let r = u.get_sync().map_err(Failure::from).and_then(|_| {
+        u.purge()
+            .map(|rows| Purged { rows })
+            .map_err(Failure::bad_request)
+    });
+    reply(StatusCode::OK, r)
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/users/{uid}/refresh",
+    params(("uid" = i64, Path, description = "User id"), RefreshQuery),
+    responses(
+        (status = 200, description = "Refreshed, if waited", body = OutcomeEnvelope),
+        (status = 202, description = "Refresh command accepted, or queued if waited", body = AcceptedEnvelope),
+        (status = 429, description = "No token to refresh now and not queued, error envelope"),
+        (status = 502, description = "Refresh failed, error envelope"),
+        (status = 503, description = "Engine unavailable, error envelope"),
+    )
+)]
+async fn refresh(uid: i64, q: RefreshQuery) -> WithStatus<Json> {
+    let cmd = Command::Refresh(uid);
+    let queue = q.queue.unwrap_or(true);
+    if q.wait.unwrap_or(false) {
+        outcome_reply(engine::ask(cmd, queue).await)
+    } else if queue {
+        reply(StatusCode::ACCEPTED, send_command(cmd.queued()).await)
+    } else {
+        reply(StatusCode::ACCEPTED, send_command(cmd).await)
+    }
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/v1/engine/silence",
+    request_body = ForceSilenceOptions,
+    responses(
+        (status = 202, description = "Silence command accepted", body = AcceptedEnvelope),
+        (status = 503, description = "Engine unavailable, error envelope"),
+    )
+)]
+async fn silence(opt: ForceSilenceOptions) -> WithStatus<Json> {
+    reply(
+        StatusCode::ACCEPTED,
+        send_command(Command::ForceSilence(opt.silence)).await,
+    )
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/maintenance",
+    responses(
+        (status = 200, description = "Report of the latest maintenance", body = MaintenanceEnvelope),
+        (status = 404, description = "Never maintained, error envelope"),
+    )
+)]
+fn last_maintenance() -> WithStatus<Json> {
+    reply(
+        StatusCode::OK,
+        db::Maintenance::last().map_err(Failure::from),
+    )
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/maintenance",
+    responses(
+        (status = 200, description = "Database pruned, checked and vacuumed", body = MaintenanceEnvelope),
+        (status = 500, description = "Error envelope"),
+    )
+)]
+async fn maintain() -> WithStatus<Json> {
+    reply(
+        StatusCode::OK,
+        engine::maintain().await.map_err(Failure::from),
+    )
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/backups",
+    responses(
+        (status = 200, description = "Database backups, latest first", body = BackupsEnvelope),
+        (status = 500, description = "Error envelope"),
+    )
+)]
+fn list_backups() -> WithStatus<Json> {
+    reply(
+        StatusCode::OK,
+        engine::backups().list().map_err(Failure::from),
+    )
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/v1/backups",
+    responses(
+        (status = 201, description = "Database backed up", body = BackupEnvelope),
+        (status = 500, description = "Error envelope"),
+    )
+)]
+async fn backup() -> WithStatus<Json> {
+    reply(
+        StatusCode::CREATED,
+        engine::backup().await.map_err(Failure::from),
+    )
+}
+
+macro_rules! json_body {
+    () => {
+        json_body!(1024 * 16)
+    };
+    ($limit:expr) => {
+        warp::body::content_length_limit($limit).and(warp::body::json())
+    };
+}
+
+/// Envelope the rejections of `/api/v1` routes.
+async fn recover(r: Rejection) -> std::result::Result<WithStatus<Json>, Infallible> {
+    #[cfg(test)]
+    if let Some(super::RouteProbe(op)) = r.find::<super::RouteProbe>() {
+        let f = Failure::new(StatusCode::OK, "route_probe", op);
+        return Ok(reply::<()>(f.status, Err(f)));
+    }
+    let f = if r.is_not_found() {
+        Failure::new(StatusCode::NOT_FOUND, "no_route", "no such api")
+    } else if let Some(e) = r.find::<warp::reject::InvalidQuery>() {
+        Failure::bad_request(e)
+    } else if let Some(e) = r.find::<warp::filters::body::BodyDeserializeError>() {
+        Failure::bad_request(e)
+    } else if let Some(e) = r.find::<warp::reject::LengthRequired>() {
+        Failure::new(StatusCode::LENGTH_REQUIRED, "length_required", e)
+    } else if let Some(e) = r.find::<warp::reject::PayloadTooLarge>() {
+        Failure::new(StatusCode::PAYLOAD_TOO_LARGE, "payload_too_large", e)
+    } else if let Some(e) = r.find::<warp::reject::MethodNotAllowed>() {
+        Failure::new(StatusCode::METHOD_NOT_ALLOWED, "method_not_allowed", e)
+    } else {
+        log::error!("unhandled api rejection: {:?}", r);
+        Failure::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "internal",
+            format!("{:?}", r),
+        )
+    };
+    Ok(reply::<()>(f.status, Err(f)))
+}
+
+pub fn routes() -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+    let users = warp::path!("users")
+        .and(warp::get())
+        .and(probe("GET /api/v1/users"))
+        .and(warp::query::<UserListQuery>())
+        .map(list_users);
+    let add_user = warp::path!("users")
+        .and(warp::post())
+        .and(probe("POST /api/v1/users"))
+        .and(json_body!())
+        .then(follow_user);
+    let user = warp::path!("users" / i64)
+        .and(warp::get())
+        .and(probe("GET /api/v1/users/{uid}"))
+        .map(get_user);
+    let del_user = warp::path!("users" / i64)
+        .and(warp::delete())
+        .and(probe("DELETE /api/v1/users/{uid}"))
+        .then(unfollow_user);
+    let purge = warp::path!("users" / i64 / "purge")
+        .and(warp::post())
+        .and(probe("POST /api/v1/users/{uid}/purge"))
+        .map(purge_user);
+    let refresh_user = warp::path!("users" / i64 / "refresh")
+        .and(warp::post())
+        .and(probe("POST /api/v1/users/{uid}/refresh"))
+        .and(warp::query::<RefreshQuery>())
+        .then(refresh);
+    let videos_of_user = warp::path!("users" / i64 / "videos")
+        .and(warp::get())
+        .and(probe("GET /api/v1/users/{uid}/videos"))
+        .and(warp::query::<PageQuery>())
+        .map(user_videos);
+    let history_of_user = warp::path!("users" / i64 / "history")
+        .and(warp::get())
+        .and(probe("GET /api/v1/users/{uid}/history"))
+        .map(user_history);
+    let note_of_user = warp::path!("users" / i64 / "note")
+        .and(warp::get())
+        .and(probe("GET /api/v1/users/{uid}/note"))
+        .map(user_note);
+    let set_note = warp::path!("users" / i64 / "note")
+        .and(warp::put())
+        .and(probe("PUT /api/v1/users/{uid}/note"))
+        .and(json_body!())
+        .map(set_user_note);
+    let notes = warp::path!("notes")
+        .and(warp::get())
+        .and(probe("GET /api/v1/notes"))
+        .map(list_notes);
+    let set_notes = warp::path!("notes")
+        .and(warp::put())
+        .and(probe("PUT /api/v1/notes"))
+        .and(json_body!(1024 * 1024 * 4))
+        .map(import_notes);
+    let mute_of_user = warp::path!("users" / i64 / "mute")
+        .and(warp::put())
+        .and(probe("PUT /api/v1/users/{uid}/mute"))
+        .and(json_body!())
+        .map(mute_user);
+    let unmute = warp::path!("users" / i64 / "mute")
+        .and(warp::delete())
+        .and(probe("DELETE /api/v1/users/{uid}/mute"))
+        .map(unmute_user);
+    let stats_of_user = warp::path!("users" / i64 / "stats")
+        .and(warp::get())
+        .and(probe("GET /api/v1/users/{uid}/stats"))
+        .and(warp::query::<StatsQuery>())
+        .map(user_stats);
+    let dynamics_of_user = warp::path!("users" / i64 / "dynamics")
+        .and(warp::get())
+        .and(probe("GET /api/v1/users/{uid}/dynamics"))
+        .and(warp::query::<PageQuery>())
+        .map(user_dynamics);
+    let dynamics = warp::path!("dynamics")
+        .and(warp::get())
+        .and(probe("GET /api/v1/dynamics"))
+        .and(warp::query::<DynamicListQuery>())
+        .map(list_dynamics);
+
+    let filters = warp::path!("filters")
+        .and(warp::get())
+        .and(probe("GET /api/v1/filters"))
+        .map(list_filters);
+    let create_filter = warp::path!("filters")
+        .and(warp::post())
+        .and(probe("POST /api/v1/filters"))
+        .and(json_body!())
+        .map(new_filter);
+    let filter_query = warp::path!("filters" / i64 / "query")
+        .and(warp::put())
+        .and(probe("PUT /api/v1/filters/{fid}/query"))
+        .and(json_body!())
+        .map(set_filter_query);
+    let users_of_filter = warp::path!("filters" / i64 / "users")
+        .and(warp::get())
+        .and(probe("GET /api/v1/filters/{fid}/users"))
+        .and(warp::query::<PageQuery>())
+        .map(filter_users);
+    let join = warp::path!("filters" / i64 / "users" / i64)
+        .and(warp::put())
+        .and(probe("PUT /api/v1/filters/{fid}/users/{uid}"))
+        .and(json_body!())
+        .map(join_filter);
+    let leave = warp::path!("filters" / i64 / "users" / i64)
+        .and(warp::delete())
+        .and(probe("DELETE /api/v1/filters/{fid}/users/{uid}"))
+        .map(leave_filter);
+
+    let videos = warp::path!("videos")
+        .and(warp::get())
+        .and(probe("GET /api/v1/videos"))
+        .and(warp::query::<VideoListQuery>())
+        .map(list_videos);
+    let videos_state = warp::path!("videos" / "state")
+        .and(warp::post())
+        .and(probe("POST /api/v1/videos/state"))
+        .and(json_body!())
+        .map(mark_videos);
+
+    let lives = warp::path!("lives")
+        .and(warp::get())
+        .and(probe("GET /api/v1/lives"))
+        .and(warp::query::<UserListQuery>())
+        .map(list_lives);
+
+    let status = warp::path!("engine" / "status")
+        .and(warp::get())
+        .and(probe("GET /api/v1/engine/status"))
+        .map(engine_status);
+    let engine_silence = warp::path!("engine" / "silence")
+        .and(warp::put())
+        .and(probe("PUT /api/v1/engine/silence"))
+        .and(json_body!())
+        .then(silence);
+
+    let maintenance = warp::path!("maintenance")
+        .and(warp::get())
+        .and(probe("GET /api/v1/maintenance"))
+        .map(last_maintenance);
+    let run_maintenance = warp::path!("maintenance")
+        .and(warp::post())
+        .and(probe("POST /api/v1/maintenance"))
+        .then(maintain);
+
+    let backups = warp::path!("backups")
+        .and(warp::get())
+        .and(probe("GET /api/v1/backups"))
+        .map(list_backups);
+    let run_backup = warp::path!("backups")
+        .and(warp::post())
+        .and(probe("POST /api/v1/backups"))
+        .then(backup);
+
+    let jobs = warp::path!("jobs")
+        .and(warp::get())
+        .and(probe("GET /api/v1/jobs"))
+        .map(list_jobs);
+    let del_job = warp::path!("jobs" / i64)
+        .and(warp::delete())
+        .and(probe("DELETE /api/v1/jobs/{uid}"))
+        .map(cancel_job);
+
+    let v1 = users
+        .or(add_user)
+        .unify()
+        .or(user)
+        .unify()
+        .or(del_user)
+        .unify()
+        .or(refresh_user)
+        .unify()
+        .or(purge)
+        .unify()
+        .or(videos_of_user)
+        .unify()
+        .or(history_of_user)
+        .unify()
+        .or(stats_of_user)
+        .unify()
+        .or(note_of_user)
+        .unify()
+        .or(set_note)
+        .unify()
+        .or(notes)
+        .or(set_notes)
+        .unify()
+        .or(mute_of_user)
+        .unify()
+        .or(unmute)
+        .unify()
+        .or(dynamics_of_user)
+        .unify()
+        .or(dynamics)
+        .unify()
+        .or(filters)
+        .unify()
+        .or(create_filter)
+        .unify()
+        .or(filter_query)
+        .unify()
+        .or(users_of_filter)
+        .unify()
+        .or(join)
+        .unify()
+        .or(leave)
+        .unify()
+        .or(videos)
+        .unify()
+        .or(videos_state)
+        .unify()
+        .or(lives)
+        .unify()
+        .or(status)
+        .unify()
+        .or(engine_silence)
+        .unify()
+        .or(maintenance)
+        .unify()
+        .or(run_maintenance)
+        .unify()
+        .or(backups)
+        .unify()
+        .or(run_backup)
+        .unify()
+        .or(jobs)
+        .unify()
+        .or(del_job)
+        .unify();
+
+    warp::path!("api" / "v1" / ..).and(v1.recover(recover).unify())
+}
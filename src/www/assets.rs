@@ -0,0 +1,228 @@
+//! Templates and static files, embedded at compile time so that the binary runs from any
+//! working directory. With `assets_dir` configured, they are read from there instead and
+//! reloaded on every request, for development.
+use super::img;
+use crate::{
+    config,
+    i18n::{self, Locale},
+};
+use serde_derive::Deserialize;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::sync::RwLock;
+use tera::{Context as TeraContext, Tera};
+use warp::{http::StatusCode, reply::Response, Reply};
+
+macro_rules! embed {
+    (@str $dir:literal: $($name:literal),* $(,)?) => {
+        &[$(($name, include_str!(concat!("../../", $dir, "/", $name)))),*]
+    };
+    (@bytes $dir:literal: $($name:literal),* $(,)?) => {
+        &[$(($name, include_bytes!(concat!("../../", $dir, "/", $name)) as &[u8])),*]
+    };
+}
+
+const TEMPLATE_FILES: &[(&str, &str)] = embed!(@str "templates":
+    "api_docs.html",
+    "dynamic_cards.html",
+    "failure.html",
+    "filter_options.html",
+    "index.html",
+    "jobs.html",
+    "later.html",
+    "user.html",
+    "user_cards.html",
+    "user_history.html",
+    "video_cards.html",
+);
+
+const STATIC_FILES: &[(&str, &[u8])] = embed!(@bytes "static":
+    "common.js",
+    "favicon.ico",
+    "index.js",
+    "redoc.standalone.js",
+    "user.js",
+);
+
+/// Static files are cached for long when requested with their content hash as `v` .
+const IMMUTABLE_CACHE: &str = "public, max-age=31536000, immutable";
+
+lazy_static::lazy_static! {
+    static ref TEMPLATES: RwLock<Tera> = RwLock::new(load_templates());
+
+    /// Content hashes of the embedded static files.
+    static ref STATIC_HASHES: HashMap<&'static str, String> = STATIC_FILES
+        .iter()
+        .map(|(name, bytes)| (*name, hash_of(bytes)))
+        .collect();
+}
+
+fn hash_of(bytes: &[u8]) -> String {
+    let mut h = DefaultHasher::new();
+    bytes.hash(&mut h);
+    format!("{:016x}", h.finish())
+}
+
+fn override_dir() -> Option<&'static str> {
+    config::get().assets_dir.as_deref()
+}
+
+fn load_templates() -> Tera {
+    let loaded = match override_dir() {
+        Some(dir) => Tera::new(&format!("{}/templates/**/*.html", dir)),
+        None => {
+            let mut t = Tera::default();
+            t.add_raw_templates(TEMPLATE_FILES.to_vec()).map(|_| t)
+        }
+    };
+    let mut t = match loaded {
+        Ok(t) => t,
+        Err(e) => {
+            log::error!("Parsing error(s): {}", e);
+            ::std::process::exit(1);
+        }
+    };
+    t.register_filter("img", img::filter);
+    t.register_filter("filter_name", i18n::tera_filter_name);
+    t.register_function("static_url", static_url);
+    t
+}
+
+/// Render template _name_ , texts of the current locale are given as `tr` besides _ctx_ .
+pub fn render(name: &str, ctx: &TeraContext) -> tera::Result<String> {
+    let locale = Locale::current();
+    let mut ctx = ctx.clone();
+    ctx.insert("tr", locale.catalog());
+    ctx.insert("locale", locale.tag());
+    let ctx = &ctx;
+    if override_dir().is_some() {
+        let mut t = TEMPLATES
+            .write()
+            .unwrap_or_else(|e| panic!("Templates access error(s): {}", e));
+        t.full_reload()?;
+        return t.render(name, ctx);
+    }
+    TEMPLATES
+        .read()
+        .unwrap_or_else(|e| panic!("Templates access error(s): {}", e))
+        .render(name, ctx)
+}
+
+/// Whether _name_ is a plain file name, which cannot reach out of its directory.
+fn is_plain_name(name: &str) -> bool {
+    !name.is_empty() && !name.contains(|c: char| c == '/' || c == '\\') && !name.contains("..")
+}
+
+/// Static file _name_ with its content hash.
+fn static_file(name: &str) -> Option<(Vec<u8>, String)> {
+    if !is_plain_name(name) {
+        return None;
+    }
+    match override_dir() {
+        Some(dir) => std::fs::read(format!("{}/static/{}", dir, name))
+            .ok()
+            .map(|bytes| {
+                let hash = hash_of(&bytes);
+                (bytes, hash)
+            }),
+        None => STATIC_FILES
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|(n, bytes)| (bytes.to_vec(), STATIC_HASHES[n].clone())),
+    }
+}
+
+/// Template function `static_url(name="index.js")` , url of a static file with its content
+/// hash.
+fn static_url(args: &HashMap<String, tera::Value>) -> tera::Result<tera::Value> {
+    let name = args
+        .get("name")
+        .and_then(|v| v.as_str())
+        .ok_or_else(|| tera::Error::msg("static_url requires a `name`"))?;
+    let url = match static_file(name) {
+        Some((_, hash)) => format!("/static/{}?v={}", name, hash),
+        None => return Err(tera::Error::msg(format!("no static file `{}`", name))),
+    };
+    Ok(tera::Value::String(url))
+}
+
+fn mime_of(name: &str) -> &'static str {
+    match name.rsplit('.').next() {
+        Some("js") => "application/javascript; charset=utf-8",
+        Some("css") => "text/css; charset=utf-8",
+        Some("ico") => "image/x-icon",
+        Some("png") => "image/png",
+        Some("svg") => "image/svg+xml",
+        Some("json") => "application/json",
+        _ => "application/octet-stream",
+    }
+}
+
+#[derive(Debug, Deserialize, Default)]
+pub struct StaticQuery {
+    v: Option<String>,
+}
+
+pub fn serve_static(name: String, q: StaticQuery) -> Response {
+    let (bytes, hash) = match static_file(&name) {
+        Some(f) => f,
+        None => {
+            return warp::reply::with_status(
+                format!("Err: no static file {}", name),
+                StatusCode::NOT_FOUND,
+            )
+            .into_response()
+        }
+    };
+    let cache = match q.v {
+        Some(v) if v == hash => IMMUTABLE_CACHE,
+        _ => "no-cache",
+    };
+    warp::http::Response::builder()
+        .header("content-type", mime_of(&name))
+        .header("cache-control", cache)
+        .header("etag", format!("\"{}\"", hash))
+        .body(bytes.into())
+        .unwrap_or_else(|e| {
+            warp::reply::with_status(e.to_string(), StatusCode::INTERNAL_SERVER_ERROR)
+                .into_response()
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn files_in(dir: &str) -> Vec<String> {
+        let mut names: Vec<String> =
+            std::fs::read_dir(format!("{}/{}", env!("CARGO_MANIFEST_DIR"), dir))
+                .unwrap()
+                .filter_map(|e| e.ok())
+                .filter(|e| e.path().is_file())
+                .map(|e| e.file_name().to_string_lossy().into_owned())
+                .collect();
+        names.sort();
+        names
+    }
+
+    /// Every file of `templates/` and `static/` must be embedded.
+    #[test]
+    fn embedded_files_are_complete() {
+        let mut templates: Vec<String> =
+            TEMPLATE_FILES.iter().map(|(n, _)| n.to_string()).collect();
+        templates.sort();
+        assert_eq!(templates, files_in("templates"));
+        let mut statics: Vec<String> = STATIC_FILES.iter().map(|(n, _)| n.to_string()).collect();
+        statics.sort();
+        assert_eq!(statics, files_in("static"));
+    }
+
+    #[test]
+    fn plain_names_only() {
+        assert!(is_plain_name("index.js"));
+        for name in ["", "..", "../cache.db3", "a/b.js", "a\\b.js", "..index.js"] {
+            assert!(!is_plain_name(name), "{}", name);
+        }
+    }
+}
@@ -0,0 +1,139 @@
+//! Local cache of remote avatars, covers and dynamic images. Pages refer to an image as
+//! `/img/<hash>` through the `img` template filter, the image is fetched on the first request
+//! and kept on disk within the configured size.
+use crate::{config, db, engine::remote::Remote, Result};
+use chrono::Utc;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use warp::{http::StatusCode, reply::Response, Reply};
+
+/// Registered images are registered again after this long, keeping unfetched ones from being
+/// pruned while still shown.
+const REGISTER_AGAIN_SECS: i64 = 24 * 3600;
+
+lazy_static::lazy_static! {
+    static ref REMOTE: Remote = Remote::new();
+
+    /// Hashes registered since startup with the time, saving a db write per rendered image.
+    static ref REGISTERED: Mutex<HashMap<String, i64>> = Mutex::new(HashMap::new());
+}
+
+/// 64-bit FNV-1a of _url_ , stable across builds as it names files and database rows.
+fn hash_of(url: &str) -> String {
+    let h = url.bytes().fold(0xcbf2_9ce4_8422_2325_u64, |h, b| {
+        (h ^ b as u64).wrapping_mul(0x0000_0100_0000_01b3)
+    });
+    format!("{:016x}", h)
+}
+
+fn dir() -> PathBuf {
+    PathBuf::from(&config::get().image_cache.dir)
+}
+
+/// Local url of remote image _url_ , or _url_ itself if not cached.
+pub fn local_url(url: &str) -> String {
+    let url = match url {
+        u if u.starts_with("//") => format!("https:{}", u),
+        u if u.starts_with("http://") || u.starts_with("https://") => u.to_string(),
+        u => return u.to_string(),
+    };
+    if !config::get().image_cache.enabled {
+        return url;
+    }
+    let hash = hash_of(&url);
+    let mut registered = REGISTERED
+        .lock()
+        .unwrap_or_else(|e| panic!("Image cache access error(s): {}", e));
+    let now = Utc::now().timestamp();
+    if registered
+        .get(&hash)
+        .map_or(true, |t| now - t >= REGISTER_AGAIN_SECS)
+    {
+        if let Err(e) = db::CachedImage::register(&hash, &url) {
+            log::error!("Register image {} error(s): {}", url, e);
+            return url;
+        }
+        registered.insert(hash.clone(), now);
+    }
+    format!("/img/{}", hash)
+}
+
+/// Template filter `img(size="@128w_128h_1o.webp")` , the optional _size_ is a CDN resize
+/// suffix appended to the url.
+pub fn filter(
+    value: &tera::Value,
+    args: &HashMap<String, tera::Value>,
+) -> tera::Result<tera::Value> {
+    let url = tera::try_get_value!("img", "value", String, value);
+    let size = args.get("size").and_then(|v| v.as_str()).unwrap_or("");
+    Ok(tera::Value::String(local_url(&format!("{}{}", url, size))))
+}
+
+async fn load(hash: &str) -> Result<(String, Vec<u8>)> {
+    let img = db::CachedImage::get(hash)?;
+    let path = dir().join(hash);
+    if img.bytes > 0 {
+        match tokio::fs::read(&path).await {
+            Ok(bytes) => {
+                db::CachedImage::touch(hash)
+                    .map_err(|e| log::warn!("Touch image {} error(s): {}", hash, e))
+                    .ok();
+                return Ok((img.mime, bytes));
+            }
+            Err(e) => log::warn!("Read image {} error(s): {}, fetch again", path.display(), e),
+        }
+    }
+    let (mime, bytes) = REMOTE.image(&img.url).await?;
+    tokio::fs::create_dir_all(dir()).await?;
+    tokio::fs::write(&path, &bytes).await?;
+    let max_bytes = config::get().image_cache.max_mb * 1024 * 1024;
+    for h in db::CachedImage::stored(hash, &mime, bytes.len() as i64, max_bytes)? {
+        tokio::fs::remove_file(dir().join(&h))
+            .await
+            .map_err(|e| log::warn!("Remove evicted image {} error(s): {}", h, e))
+            .ok();
+    }
+    Ok((mime, bytes))
+}
+
+pub async fn serve(hash: String) -> Response {
+    if !hash.chars().all(|c| c.is_ascii_hexdigit()) {
+        return warp::reply::with_status(
+            format!("Err: bad image hash {}", hash),
+            StatusCode::NOT_FOUND,
+        )
+        .into_response();
+    }
+    match load(&hash).await {
+        Ok((mime, bytes)) => warp::http::Response::builder()
+            .header("content-type", mime)
+            // a hash always refers to the same url
+            .header("cache-control", "public, max-age=2592000")
+            .body(bytes.into())
+            .unwrap_or_else(|e| {
+                warp::reply::with_status(e.to_string(), StatusCode::INTERNAL_SERVER_ERROR)
+                    .into_response()
+            }),
+        Err(e) => {
+            let code = match e.downcast_ref::<rusqlite::Error>() {
+                Some(rusqlite::Error::QueryReturnedNoRows) => StatusCode::NOT_FOUND,
+                _ if e.downcast_ref::<reqwest::Error>().is_some() => StatusCode::BAD_GATEWAY,
+                _ => StatusCode::INTERNAL_SERVER_ERROR,
+            };
+            log::warn!("Serve image {} error(s): {}", hash, e);
+            warp::reply::with_status(format!("Err: {}", e), code).into_response()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hash_is_stable() {
+        assert_eq!(hash_of(""), "cbf29ce484222325");
+        assert_eq!(hash_of("a"), "af63dc4c8601ec8c");
+    }
+}
@@ -0,0 +1,195 @@
+//! OpenAPI 3 description of the JSON routes, served at `/api/openapi.json`.
+use super::{
+    api, CancelJobOptions, FilterQueryOptions, FollowOptions, ForceSilenceOptions,
+    ModFilterOptions, MuteOptions, NewFilterOptions, NoteOptions, PurgeOptions, RefreshOptions,
+    VideoStateOptions,
+};
+use crate::{db, engine};
+use utoipa::OpenApi;
+
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "hobob",
+        description = "An web app for hobo kicked from bibi&lili."
+    ),
+    paths(
+        super::op_follow,
+        super::op_refresh,
+        super::op_silence,
+        super::op_mod_filter,
+        super::op_new_filter,
+        super::op_filter_query,
+        super::op_video_state,
+        super::op_cancel_job,
+        super::op_note,
+        super::op_mute,
+        super::op_purge,
+        super::get_user,
+        super::get_vlist,
+        super::get_flist,
+        super::get_history,
+        super::get_stats,
+        super::get_note,
+        super::get_jobs,
+        super::list,
+        api::list_users,
+        api::follow_user,
+        api::get_user,
+        api::unfollow_user,
+        api::refresh,
+        api::user_videos,
+        api::user_history,
+        api::user_stats,
+        api::user_note,
+        api::set_user_note,
+        api::list_notes,
+        api::import_notes,
+        api::mute_user,
+        api::unmute_user,
+        api::purge_user,
+        api::user_dynamics,
+        api::list_dynamics,
+        api::list_filters,
+        api::new_filter,
+        api::set_filter_query,
+        api::filter_users,
+        api::join_filter,
+        api::leave_filter,
+        api::list_videos,
+        api::mark_videos,
+        api::list_lives,
+        api::engine_status,
+        api::list_jobs,
+        api::cancel_job,
+        api::silence,
+        api::last_maintenance,
+        api::maintain,
+        super::op_maintain,
+        api::list_backups,
+        api::backup,
+        super::op_backup,
+    ),
+    components(schemas(
+        FollowOptions,
+        RefreshOptions,
+        ForceSilenceOptions,
+        ModFilterOptions,
+        NewFilterOptions,
+        FilterQueryOptions,
+        VideoStateOptions,
+        CancelJobOptions,
+        NoteOptions,
+        MuteOptions,
+        PurgeOptions,
+        db::UserInfo,
+        db::UserInfoHistory,
+        db::UserSync,
+        db::SyncStatus,
+        db::VideoInfo,
+        db::VideoState,
+        db::StatedVideo,
+        db::FilterMeta,
+        db::FilterCounts,
+        db::UserQuerySpec,
+        db::JobKind,
+        db::RefreshJob,
+        engine::Event,
+        engine::Outcome,
+        engine::live::LiveLatency,
+        db::DynamicInfo,
+        db::UserStat,
+        db::UserNote,
+        db::MaintenanceReport,
+        db::BackupFile,
+        api::ApiError,
+        api::FollowRequest,
+        api::MembershipRequest,
+        api::MuteRequest,
+        api::UserItem,
+        api::Accepted,
+        api::Purged,
+        api::UserItemPage,
+        api::UserItemEnvelope,
+    ))
+)]
+struct ApiDoc;
+
+pub fn spec() -> utoipa::openapi::OpenApi {
+    ApiDoc::openapi()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{RouteProbe, PROBED, PROBE_HEADER};
+    use std::collections::BTreeSet;
+    use utoipa::openapi::PathItemType;
+    use warp::{Filter, Rejection};
+
+    /// Reply the operation which stopped a probing request.
+    async fn probed(r: Rejection) -> Result<String, Rejection> {
+        match r.find::<RouteProbe>() {
+            Some(RouteProbe(op)) => Ok(format!("route_probe {}", op)),
+            None => Err(r),
+        }
+    }
+
+    fn documented() -> BTreeSet<String> {
+        let mut ops = BTreeSet::new();
+        for (path, item) in super::spec().paths.paths.iter() {
+            for typ in item.operations.keys() {
+                let method = match typ {
+                    PathItemType::Get => "GET",
+                    PathItemType::Post => "POST",
+                    PathItemType::Put => "PUT",
+                    PathItemType::Delete => "DELETE",
+                    _ => panic!("unexpected method {:?} of {}", typ, path),
+                };
+                ops.insert(format!("{} {}", method, path));
+            }
+        }
+        ops
+    }
+
+    /// Every documented operation must be routed, and every probed route documented.
+    #[test]
+    fn spec_matches_probed_routes() {
+        let _ = super::super::routes();
+        let probed: BTreeSet<String> = PROBED
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|op| op.to_string())
+            .collect();
+        assert_eq!(documented(), probed);
+    }
+
+    /// A probing request to every documented operation, with all path parameters set to 0,
+    /// shall be stopped by the probe of that very operation, before its handler runs.
+    #[tokio::test]
+    async fn spec_operations_are_routed() {
+        let app = super::super::routes().recover(probed);
+        for op in documented() {
+            let (method, path) = op.split_once(' ').unwrap();
+            let uri = path
+                .split('/')
+                .map(|seg| if seg.starts_with('{') { "0" } else { seg })
+                .collect::<Vec<_>>()
+                .join("/");
+            let res = warp::test::request()
+                .method(method)
+                .path(&uri)
+                .header(PROBE_HEADER, "1")
+                .reply(&app)
+                .await;
+            let body = String::from_utf8_lossy(res.body());
+            assert!(
+                body.contains("route_probe") && body.contains(&op),
+                "{} documented but not routed: {} {}",
+                op,
+                res.status(),
+                body
+            );
+        }
+    }
+}
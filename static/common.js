@@ -0,0 +1,58 @@
+// fill placeholders {0}, {1}... of catalog text with the rest arguments
+function fmt(text) {
+    var args = arguments;
+    return text.replace(/\{(\d+)\}/g, function (m, i) {
+        return args[+i + 1];
+    });
+}
+
+function do_post_json(url, data, success, complete) {
+    if (!complete) {
+        complete = function (xml, status) {
+            console.log("final", status);
+        };
+    }
+    if (!success) {
+        success = function (d) {
+            console.log("succ", d);
+        };
+    }
+    $.ajax({
+        type: "post",
+        url: url,
+        dataType : "json",
+        contentType : "application/json",
+        data: JSON.stringify(data),
+        complete: complete,
+        success: success,
+    });
+}
+
+function on_video_state(vid, state) {
+    do_post_json('/op/video/state', {
+        vids: [vid],
+        state: state,
+    }, function (d) {
+        var card = $('div.video-card[data-vid="' + vid + '"]');
+        card.removeClass('video-unseen video-seen video-saved video-dismissed');
+        card.addClass('video-' + state);
+    });
+}
+
+function on_user_all_seen(id) {
+    do_post_json('/op/video/state', {
+        uid: id,
+        from: 'unseen',
+        state: 'seen',
+    }, function (d) {
+        window.location.reload();
+    });
+}
+
+function on_cancel_job(id) {
+    do_post_json('/op/job/cancel', {
+        uid: id,
+    }, function (d) {
+        $('tr#job-' + id).remove();
+    });
+}
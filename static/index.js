@@ -7,28 +7,74 @@ function reload_filters() {
     });
 }
 
+function filter_name(fid) {
+    return $('select#select-filter-type option[value="' + fid + '"]').attr('data-name');
+}
+
+function filter_label(f) {
+    var label = f.name + ' (' + f.counts.members;
+    if (f.counts.live > 0) {
+        label += fmt(TR.filter_live, f.counts.live);
+    }
+    if (f.counts.new_videos > 0) {
+        label += fmt(TR.filter_new, f.counts.new_videos);
+    }
+    return label + ')';
+}
+
+function update_filter_counts(filters) {
+    filters.forEach(function (f) {
+        var option = $('select#select-filter-type option[value="' + f.fid + '"]');
+        // names in engine events are of the server locale, keep the rendered ones
+        f.name = option.attr('data-name') || f.name;
+        option.text(filter_label(f));
+    });
+}
+
 function cur_filter() {
     return $('select#select-filter-type').val();
 }
 
 function cur_order() {
-    return cur_filter() + '/' + $('a.nav-link.active').attr('id').replace('-tab-nav', '');
+    return cur_filter() + '/' + cur_tab();
+}
+
+function cur_tab() {
+    return $('a.nav-link.active').attr('id').replace('-tab-nav', '');
+}
+
+function tab_url(start) {
+    if (cur_tab() == 'dynamic') {
+        return '/card/dynamics/' + cur_filter() + '/' + start + '/10';
+    }
+    if (cur_tab() == 'search') {
+        return '/card/search/' + start + '/10?q=' + encodeURIComponent($('input#input-search').val());
+    }
+    if (cur_tab() == 'unfollowed') {
+        return '/card/unfollowed/' + start + '/10';
+    }
+    return '/card/ulist/' + cur_order() + '/' + start + '/10';
 }
 
 function enforce_tab_load() {
     if ($('div.tab-pane.active div.d-flex div.card').length == 0) {
-        $('div.tab-pane.active div.d-flex').load('/card/ulist/' + cur_order() + '/0/10', function() {
+        $('div.tab-pane.active div.d-flex').load(tab_url(0), function() {
             update_end_status();
         });
     }
     $('span#tab-title-display').text($('a.nav-link.active').text());
-    $('span#filter-name-display').text($('select#select-filter-type option[value="' + cur_filter() + '"]').text());
+    $('span#filter-name-display').text(filter_name(cur_filter()));
 }
 
 function tabs_reload() {
     $('div#default-list-content').html('default-list');
     $('div#video-list-content').html('video-list');
     $('div#live-list-content').html('live-list');
+    $('div#unseen-list-content').html('unseen-list');
+    $('div#growth-list-content').html('growth-list');
+    $('div#dynamic-list-content').html('dynamic-list');
+    $('div#search-list-content').html('search-list');
+    $('div#unfollowed-list-content').html('unfollowed-list');
     enforce_tab_load();
 }
 
@@ -43,7 +89,7 @@ function loadmore() {
     console.log('loadmore');
     $('#loading-spinner').show();
     var start = $('div.tab-pane.active div.d-flex div.card').length;
-    $.get("/card/ulist/" + cur_order() + '/' + start + '/10', function(data, status) {
+    $.get(tab_url(start), function(data, status) {
         $('#loading-spinner').hide();
         $('div.tab-pane.active div.d-flex[role="list-content"]').append(data);
         update_end_status();
@@ -85,28 +131,6 @@ function post_forcesilence(flag) {
     });
 }
 
-function do_post_json(url, data, success, complete) {
-    if (!complete) {
-        complete = function (xml, status) {
-            console.log("final", status);
-        };
-    }
-    if (!success) {
-        success = function (d) {
-            console.log("succ", d);
-        };
-    }
-    $.ajax({
-        type: "post",
-        url: url,
-        dataType : "json",
-        contentType : "application/json",
-        data: JSON.stringify(data),
-        complete: complete,
-        success: success,
-    });
-}
-
 function on_force_arefresh() {
     post_forcesilence(false);
 }
@@ -120,8 +144,8 @@ function on_filter_changed() {
 }
 
 function on_check_unfollow(id, name) {
-    use_yorn_modal('取消关注确认',
-        '确认取消关注用户<span class="text-danger">' + name + '</span>？',
+    use_yorn_modal(TR.confirm_unfollow_title,
+        fmt(TR.confirm_unfollow, '<span class="text-danger">' + name + '</span>'),
         function() {
             do_post_json('/op/follow', {
                 enable: false,
@@ -133,10 +157,46 @@ function on_check_unfollow(id, name) {
     );
 }
 
+function on_refollow(id) {
+    do_post_json('/op/follow', {
+        enable: true,
+        uid: id,
+    }, function() {
+        tabs_reload();
+    });
+}
+
+function on_check_purge(id, name) {
+    use_yorn_modal(TR.confirm_purge_title,
+        fmt(TR.confirm_purge, '<span class="text-danger">' + name + '</span>'),
+        function() {
+            do_post_json('/op/purge', {
+                uid: id,
+            }, function() {
+                tabs_reload();
+            });
+        },
+    );
+}
+
+function on_maintain() {
+    use_yorn_modal(TR.menu_maintain, TR.maintain_desc, function() {
+        do_post_json('/op/maintain', {}, function(report) {
+            use_yorn_modal(TR.maintain_report, $('<pre>').text(JSON.stringify(report, null, 2)), function() {});
+        });
+    });
+}
+
+function on_backup() {
+    do_post_json('/op/backup', {}, function(file) {
+        use_yorn_modal(TR.backup_done, $('<pre>').text(JSON.stringify(file, null, 2)), function() {});
+    });
+}
+
 var last_filter_to_join = null;
 
 function on_ui_addto_filter(id, name) {
-    use_yorn_modal('选择列表', '添加<span class="text-danger">' + name + '</span>到' +
+    use_yorn_modal(TR.choose_filter, fmt(TR.add_to_filter_desc, '<span class="text-danger">' + name + '</span>') +
         '<select class="form-select" id="modal-select-filter-to-join"></select>', function() {
             last_filter_to_join = parseInt($('select#modal-select-filter-to-join').val());
             do_post_json('op/mod/filter', {
@@ -156,13 +216,60 @@ function on_ui_addto_filter(id, name) {
     });
 }
 
+function on_edit_note(id, name) {
+    use_yorn_modal(TR.edit_note + name,
+        '<textarea id="input-note" class="form-control mb-2" rows="3" placeholder="' + TR.note_placeholder + '"></textarea>' +
+        '<input id="input-tags" type="text" class="form-control" placeholder="' + TR.tags_placeholder + '">',
+        function() {
+            do_post_json('/op/note', {
+                uid: id,
+                note: $('textarea#input-note').val(),
+                tags: $('input#input-tags').val().split(/[,，]/),
+            }, function(d) {
+                if (d != 'success') {
+                    alert(d);
+                }
+                $('div#user-card-' + id).load('/card/one/' + id);
+            });
+        });
+    $.getJSON('/get/note/' + id, function (n) {
+        $('textarea#input-note').val(n.note);
+        $('input#input-tags').val(n.tags.join(', '));
+    });
+}
+
+function on_mute(id, secs, pause) {
+    do_post_json('/op/mute', {
+        uid: id,
+        secs: secs,
+        pause: !!pause,
+    }, function(d) {
+        if (d != 'success') {
+            alert(d);
+            return;
+        }
+        if (secs > 0) {
+            $('div#user-card-' + id).remove();
+        } else {
+            $('div#user-card-' + id).load('/card/one/' + id);
+        }
+    });
+}
+
+function on_search() {
+    $('div#search-list-content').html('search-list');
+    bootstrap.Tab.getOrCreateInstance($('a#search-tab-nav')[0]).show();
+    enforce_tab_load();
+    return false;
+}
+
 function on_drop_from_filter(id, uname) {
     var fid = parseInt(cur_filter());
     if (fid > 0) {
-        var fname = $('select#select-filter-type option[value="' + fid + '"]').text();
-        use_yorn_modal('从当前列表移除确认',
-            '确认从列表<span class="text-danger">' + fname +
-            '</span>移除用户<span class="text-danger">' + uname + '</span>？',
+        var fname = filter_name(fid);
+        use_yorn_modal(TR.confirm_drop_title,
+            fmt(TR.confirm_drop, '<span class="text-danger">' + fname + '</span>',
+                '<span class="text-danger">' + uname + '</span>'),
             function() {
                 do_post_json('/op/mod/filter', {
                     uid: id,
@@ -177,10 +284,10 @@ function on_drop_from_filter(id, uname) {
 }
 
 function on_new_user_filter() {
-    use_yorn_modal('新建列表',
+    use_yorn_modal(TR.menu_new_filter,
         '<div class="input-group">' +
-        '<span class="input-group-text">新列表名</span>' +
-        '<input id="input-new-list-name" type="text" class="form-control" placeholder="名称">' +
+        '<span class="input-group-text">' + TR.new_filter_name + '</span>' +
+        '<input id="input-new-list-name" type="text" class="form-control" placeholder="' + TR.name + '">' +
         '</div>', function() {
             do_post_json('/op/new/filter', {
                 name: $('input#input-new-list-name').val(),
@@ -200,9 +307,79 @@ function use_yorn_modal(title, desc, cb) {
     yorn_modal.show();
 }
 
+var smart_query_keys = ['any', 'all', 'not', 'is', 'order'];
+var smart_query_hint = 'any=1,2&not=3&is=live,viewers:1000,uploaded:3,name:xx&order=name';
+
+function parse_smart_query(text) {
+    var query = {};
+    new URLSearchParams(text).forEach(function (v, k) {
+        query[k] = v;
+    });
+    return query;
+}
+
+function format_smart_query(query) {
+    return smart_query_keys.filter(function (k) {
+        return query[k];
+    }).map(function (k) {
+        return k + '=' + query[k];
+    }).join('&');
+}
+
+function on_edit_smart_filter() {
+    var fid = parseInt(cur_filter());
+    $.getJSON('/get/flist', function (filters) {
+        var f = filters.find(function (f) { return f.fid == fid; });
+        if (!f || !f.query) {
+            alert(TR.not_smart_filter);
+            return;
+        }
+        use_yorn_modal(TR.edit_smart_filter + f.name,
+            '<div class="input-group">' +
+            '<span class="input-group-text">' + TR.query + '</span>' +
+            '<input id="input-edit-smart-query" type="text" class="form-control" placeholder="' + smart_query_hint + '">' +
+            '</div>', function() {
+                do_post_json('/op/filter/query', {
+                    fid: fid,
+                    query: parse_smart_query($('input#input-edit-smart-query').val()),
+                }, function(d) {
+                    if (d != 'success') {
+                        alert(d);
+                    }
+                    reload_filters();
+                    tabs_reload();
+                });
+            });
+        $('input#input-edit-smart-query').val(format_smart_query(f.query));
+    });
+}
+
+function on_new_smart_filter() {
+    use_yorn_modal(TR.menu_new_smart_filter,
+        '<div class="input-group mb-2">' +
+        '<span class="input-group-text">' + TR.name + '</span>' +
+        '<input id="input-new-smart-name" type="text" class="form-control" placeholder="' + TR.name + '">' +
+        '</div>' +
+        '<div class="input-group">' +
+        '<span class="input-group-text">' + TR.query + '</span>' +
+        '<input id="input-new-smart-query" type="text" class="form-control" placeholder="' + smart_query_hint + '">' +
+        '</div>', function() {
+            do_post_json('/op/new/filter', {
+                name: $('input#input-new-smart-name').val(),
+                query: parse_smart_query($('input#input-new-smart-query').val()),
+            }, function(d) {
+                if (d != 'success') {
+                    alert(d);
+                }
+                reload_filters();
+            });
+        });
+}
+
 function on_move2top_filter(id) {
     var fid = parseInt(cur_filter());
-    if (fid > 0) {
+    var smart = $('select#select-filter-type option:selected').attr('data-smart');
+    if (fid > 0 && !smart) {
         do_post_json('/op/mod/filter', {
             uid: id,
             fid: fid,
@@ -214,13 +391,19 @@ function on_move2top_filter(id) {
 }
 
 function on_try_refresh(id) {
-    do_post_json('/op/refresh', {
+    do_post_json('/op/refresh?wait=true&queue=true', {
         uid: id,
+    }, function (d) {
+        if (d == 'queued') {
+            push_toast('uid ' + id + ' ' + TR.sync_queued);
+        } else if (d.failed) {
+            push_toast('uid ' + id + ' ' + TR.sync_failed + '<span class="text-danger">' + d.failed + '</span>');
+        }
     });
 }
 
 function update_end_status() {
-    $('span#end-status-text').text('最近刷新' + new Date().toLocaleString());
+    $('span#end-status-text').text(TR.last_refresh + ' ' + new Date().toLocaleString());
 }
 
 function on_clear_live_toast() {
@@ -228,20 +411,23 @@ function on_clear_live_toast() {
 }
 
 var last_live_msgid = 0;
+var last_rename_msgid = 0;
+var last_dynamic_msgid = 0;
 
 function handle_ev(ev) {
     var data = JSON.parse(ev.data);
     $("span#status-display").text(data.status_desc);
     if (data.done_refresh) {
         $("span.tag-latest-sync-user").hide();
-        if (typeof data.status == 'string') {
+        if (typeof data.status[0] == 'string') {
             $('span#silence-reason-display').hide();
             $('span#silence-reason-display').text('');
         } else {
-            $('span#silence-reason-display').text(data.status.Silence[1]);
+            $('span#silence-reason-display').text(data.status[0].Silence[1]);
             $('span#silence-reason-display').show();
         }
-        $("span#status-last-sync-uid").text("最近刷新uid:" + data.done_refresh);
+        update_filter_counts(data.filters);
+        $("span#status-last-sync-uid").text(TR.last_refresh_uid + data.done_refresh);
         $("span#status-last-sync-uid").show();
         $("div#user-card-" + data.done_refresh + " span.tag-latest-sync-user").show();
         var card = $("div#user-card-" + data.done_refresh);
@@ -250,18 +436,47 @@ function handle_ev(ev) {
         }
         onResize();
     }
+    update_live_latency(data.live_latency);
     if (data.new_live_msgid > last_live_msgid) {
         console.log('get new live of ' + data.new_live_name);
         last_live_msgid = data.new_live_msgid;
-        $('#toast-container-new-live div.toast.hide').remove();
-        $('#toast-container-new-live').append('<div class="toast align-items-center show" role="alert" aria-live="assertive" aria-atomic="true" style="z-index: 11">' +
+        push_toast(fmt(TR.toast_live, '<span class="text-danger">' + data.new_live_name + '</span>'));
+    }
+    if (data.new_dynamic_msgid > last_dynamic_msgid) {
+        console.log('get new dynamic of ' + data.new_dynamic_uid);
+        last_dynamic_msgid = data.new_dynamic_msgid;
+        push_toast(fmt(TR.toast_dynamic, '<span class="text-danger">' + data.new_dynamic_name + '</span>') +
+            $('<span>').text(data.new_dynamic_text.substring(0, 40)).html());
+    }
+    if (data.rename_msgid > last_rename_msgid) {
+        console.log('get rename of ' + data.rename_uid);
+        last_rename_msgid = data.rename_msgid;
+        push_toast(fmt(TR.toast_rename, '<span class="text-danger">' + data.rename_from + '</span>',
+            '<span class="text-danger">' + data.rename_to + '</span>'));
+    }
+}
+
+function update_live_latency(lat) {
+    if (lat.bound_secs <= 0) {
+        return;
+    }
+    var text = fmt(TR.live_latency, lat.bound_secs);
+    if (lat.samples > 0) {
+        text += fmt(TR.live_latency_recent, lat.last_secs, lat.max_secs);
+    }
+    $('span#live-latency-display').text(text);
+    $('span#live-latency-display').show();
+}
+
+function push_toast(body) {
+    $('#toast-container-new-live div.toast.hide').remove();
+    $('#toast-container-new-live').append('<div class="toast align-items-center show" role="alert" aria-live="assertive" aria-atomic="true" style="z-index: 11">' +
   '<div class="d-flex">' +
     '<div class="toast-body">' +
-            '<span class="text-danger">' + data.new_live_name + '</span>开播' +
+            body +
    '</div>' +
     '<button type="button" class="btn-close me-2 m-auto" data-bs-dismiss="toast" aria-label="Close"></button>' +
   '</div></div>');
-    }
 }
 
 function onResize() {
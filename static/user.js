@@ -0,0 +1,63 @@
+function on_user_refresh(id) {
+    do_post_json('/op/refresh?wait=true&queue=true', {
+        uid: id,
+    }, function (d) {
+        if (d == 'queued') {
+            alert(TR.sync_queued);
+        } else if (d.failed) {
+            alert(TR.sync_failed + d.failed);
+        }
+    });
+}
+
+function on_user_filter_changed(id, fid, checked) {
+    do_post_json('/op/mod/filter', {
+        uid: id,
+        fid: fid,
+        priority: checked ? Date.now() : -1,
+    });
+}
+
+function stat_label(ts) {
+    var d = new Date(ts * 1000);
+    return (d.getMonth() + 1) + '/' + d.getDate() + ' ' + d.getHours() + ':'
+        + ('0' + d.getMinutes()).slice(-2);
+}
+
+function draw_stat_chart(id, label, stats, key) {
+    var points = stats.filter(function (s) { return s[key] != null; });
+    new Chart(document.getElementById(id), {
+        type: 'line',
+        data: {
+            labels: points.map(function (s) { return stat_label(s.timestamp); }),
+            datasets: [{
+                label: label,
+                data: points.map(function (s) { return s[key]; }),
+                borderColor: '#0d6efd',
+                pointRadius: 1,
+            }],
+        },
+    });
+}
+
+function load_stats(uid) {
+    $.getJSON('/get/stats/' + uid, function (stats) {
+        draw_stat_chart('chart-followers', TR.chart_followers, stats, 'followers');
+        draw_stat_chart('chart-videos', TR.chart_videos, stats, 'videos');
+        draw_stat_chart('chart-live-viewers', TR.chart_live_viewers, stats, 'live_viewers');
+    });
+}
+
+var evsrc = null;
+
+$(function() {
+    var uid = parseInt($('div#user-page').attr('data-uid'));
+    load_stats(uid);
+    evsrc = new EventSource("/ev/engine");
+    evsrc.onmessage = function(event) {
+        var data = JSON.parse(event.data);
+        if (data.done_refresh == uid) {
+            window.location.reload();
+        }
+    };
+})